@@ -35,9 +35,41 @@
 //!}
 //!
 //!```
-use std::io::prelude::*;
 use std::path::Path;
 
+#[cfg(feature = "async")]
+mod async_file;
+mod backend;
+mod chunks;
+mod copy;
+#[cfg(unix)]
+mod disk;
+mod follow;
+#[cfg(unix)]
+mod lock;
+mod memory;
+mod store;
+mod sync;
+#[cfg(target_os = "linux")]
+mod xattr;
+pub mod csv;
+pub mod tar;
+
+#[cfg(feature = "async")]
+pub use async_file::*;
+pub use backend::*;
+pub use chunks::*;
+pub use copy::*;
+#[cfg(unix)]
+pub use disk::*;
+#[cfg(unix)]
+pub use lock::*;
+pub use memory::*;
+pub use store::*;
+pub use sync::*;
+#[cfg(target_os = "linux")]
+pub use xattr::*;
+
 /// Create and open the file
 pub const O_CREATE: u32 = 1 << 1;
 /// Append only
@@ -63,25 +95,81 @@ pub const SEEK_END: i32 = 3;
 #[allow(non_camel_case_types)]
 type int = i32;
 
+thread_local! {
+    static LAST_ERROR: std::cell::RefCell<std::io::Error> =
+        std::cell::RefCell::new(std::io::Error::new(std::io::ErrorKind::Other, ""));
+}
+
+/// Returns the last error of the module functions in current thread, like `errno` of C
+pub fn last_error() -> std::io::Error {
+    return LAST_ERROR.with(|e| {
+        let e = e.borrow();
+        match e.raw_os_error() {
+            Some(code) => std::io::Error::from_raw_os_error(code),
+            None => std::io::Error::new(e.kind(), e.to_string()),
+        }
+    });
+}
+
+// record the error for last_error(), returns -1
+pub(crate) fn fail(e: std::io::Error) -> int {
+    LAST_ERROR.with(|last| *last.borrow_mut() = e);
+    return -1;
+}
+
 /// Create directories recursively
 pub fn mkdir<P: AsRef<Path>>(path: P) -> int {
-    if let Err(_) = std::fs::create_dir_all(path) {
-        return -1;
+    if let Err(e) = backend::current().mkdir(path.as_ref()) {
+        return fail(e);
     }
     return 0;
 }
 
 /// Remove a file
 pub fn remove<T: AsRef<Path>>(path: T) -> int {
-    if let Err(_) = std::fs::remove_file(path) {
-        return -1;
+    if let Err(e) = backend::current().remove(path.as_ref()) {
+        return fail(e);
+    }
+    return 0;
+}
+
+/// Rename a file, the destination will be replaced
+pub fn rename<F: AsRef<Path>, T: AsRef<Path>>(from: F, to: T) -> int {
+    if let Err(e) = backend::current().rename(from.as_ref(), to.as_ref()) {
+        return fail(e);
     }
     return 0;
 }
 
+// Generates the fixed-width read_*/write_* helpers of File
+macro_rules! binary_methods {
+    ($($read:ident, $write:ident, $ty:ty, $from:ident, $to:ident, $doc:expr;)*) => {
+        $(
+            #[doc = concat!("Read ", $doc, ", returns 0 on success, -1 on short read or error")]
+            pub fn $read(&mut self, value: &mut $ty) -> int {
+                let mut buf = [0; std::mem::size_of::<$ty>()];
+                if self.read_exact(&mut buf) != 0 {
+                    return -1;
+                }
+                *value = <$ty>::$from(buf);
+                return 0;
+            }
+
+            #[doc = concat!("Write ", $doc, ", returns 0 on success, -1 on short write or error")]
+            pub fn $write(&mut self, value: $ty) -> int {
+                return self.write_exact(&value.$to());
+            }
+        )*
+    };
+}
+
 /// Intuitive File
+///
+/// The File is `Send`, it could be moved into another thread. To work on the same file from
+/// several threads, give each thread a [`File::dup`] of it. The duplicates share the position,
+/// so use [`File::read_at`] and [`File::write_at`] when the threads need their own positions.
 pub struct File {
-    pod: Option<std::fs::File>,
+    pod: Option<Box<dyn Handle>>,
     path: String,
     flags: u32,
     error: std::io::Error,
@@ -100,23 +188,20 @@ impl File {
 
     /// Open a file with given flags
     pub fn open<T: AsRef<str>>(&mut self, path: T, flags: u32) -> int {
-        let mut options = std::fs::File::options();
+        return self.open_path(Path::new(path.as_ref()), flags);
+    }
 
+    // open by a path which may not be UTF-8
+    pub(crate) fn open_path(&mut self, path: &Path, flags: u32) -> int {
         self.pod = None;
-        self.path = path.as_ref().to_string();
+        self.path = path.to_string_lossy().to_string();
 
         self.flags = flags;
         if flags == 0 {
             self.flags |= O_READ;
         }
 
-        options.create(self.flags & O_CREATE != 0);
-        options.append(self.flags & O_APPEND != 0);
-        options.read(self.flags & O_READ != 0);
-        options.write(self.flags & O_WRITE != 0);
-        options.truncate(self.flags & O_TRUNCATE != 0);
-
-        let ret = options.open(path.as_ref());
+        let ret = backend::current().open(path, self.flags);
         match ret {
             Ok(f) => {
                 self.pod = Some(f);
@@ -145,6 +230,11 @@ impl File {
         return &self.error;
     }
 
+    // move the last error out
+    pub(crate) fn take_error(&mut self) -> std::io::Error {
+        return std::mem::replace(&mut self.error, std::io::Error::new(std::io::ErrorKind::Other, ""));
+    }
+
     /// Write all data unless [`O_NONBLOCK`] flag was set
     pub fn write<Buffer: AsRef<[u8]>>(&mut self, data: Buffer) -> int {
         let mut i = 0;
@@ -156,7 +246,7 @@ impl File {
         }
 
         let nb = self.flags & O_NONBLOCK != 0;
-        let mut fd = self.fd();
+        let fd = self.io();
 
         while i < n {
             let off = i as usize;
@@ -176,12 +266,134 @@ impl File {
         return i;
     }
 
+    /// Write all slices with one system call per round, unless [`O_NONBLOCK`] flag was set.
+    /// Returns the total number of bytes written.
+    pub fn writev(&mut self, bufs: &[&[u8]]) -> int {
+        let mut i = 0;
+        let mut slice = 0;
+        let mut off = 0;
+
+        if self.is_none() {
+            return -1;
+        }
+
+        let nb = self.flags & O_NONBLOCK != 0;
+
+        loop {
+            // skip the written or empty slices
+            while slice < bufs.len() && off == bufs[slice].len() {
+                slice += 1;
+                off = 0;
+            }
+            if slice == bufs.len() {
+                break;
+            }
+
+            let mut io = Vec::with_capacity(bufs.len() - slice);
+            io.push(std::io::IoSlice::new(&bufs[slice][off..]));
+            for buf in &bufs[slice + 1..] {
+                io.push(std::io::IoSlice::new(buf));
+            }
+
+            let ret = self.io().write_vectored(&io);
+            match ret {
+                Ok(0) => {
+                    self.error = std::io::Error::new(std::io::ErrorKind::WriteZero, "failed to write whole buffer");
+                    break;
+                }
+                Ok(mut n) => {
+                    i += n as i32;
+                    while n > 0 {
+                        let step = n.min(bufs[slice].len() - off);
+                        off += step;
+                        n -= step;
+                        if off == bufs[slice].len() && n > 0 {
+                            slice += 1;
+                            off = 0;
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.error = e;
+                    break;
+                }
+            }
+            if nb {
+                break;
+            }
+        }
+
+        return i;
+    }
+
+    /// Read data into the buffers in order with one system call per round, until all buffers are
+    /// filled or the end of file, unless [`O_NONBLOCK`] flag was set. Returns the total number of bytes read.
+    pub fn readv(&mut self, bufs: &mut [&mut [u8]]) -> int {
+        let mut i = 0;
+        let mut slice = 0;
+        let mut off = 0;
+
+        if self.is_none() {
+            return -1;
+        }
+
+        let nb = self.flags & O_NONBLOCK != 0;
+
+        loop {
+            // skip the filled or empty buffers
+            while slice < bufs.len() && off == bufs[slice].len() {
+                slice += 1;
+                off = 0;
+            }
+            if slice == bufs.len() {
+                break;
+            }
+
+            let ret = {
+                let (first, rest) = bufs[slice..].split_at_mut(1);
+                let mut io = Vec::with_capacity(rest.len() + 1);
+                io.push(std::io::IoSliceMut::new(&mut first[0][off..]));
+                for buf in rest.iter_mut() {
+                    io.push(std::io::IoSliceMut::new(buf));
+                }
+                self.io().read_vectored(&mut io)
+            };
+
+            match ret {
+                Ok(0) => {
+                    break;
+                }
+                Ok(mut n) => {
+                    i += n as i32;
+                    while n > 0 {
+                        let step = n.min(bufs[slice].len() - off);
+                        off += step;
+                        n -= step;
+                        if off == bufs[slice].len() && n > 0 {
+                            slice += 1;
+                            off = 0;
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.error = e;
+                    break;
+                }
+            }
+            if nb {
+                break;
+            }
+        }
+
+        return i;
+    }
+
     pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> i32 {
         if self.is_none() {
             return -1;
         }
 
-        match self.fd().read_to_end(buf)  {
+        match self.io().read_to_end(buf)  {
             Ok(n) => {
                 return  n as i32;
             },
@@ -200,7 +412,7 @@ impl File {
         }
 
         let mut i = 0;
-        let mut fd = self.fd();
+        let fd = self.io();
 
         let ret = fd.read(buf);
         match ret {
@@ -221,7 +433,35 @@ impl File {
             return -1;
         }
 
-        if let Err(e) = self.fd().flush() {
+        if let Err(e) = self.io().flush() {
+            self.error = e;
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Flush the data and metadata to the storage, like `fsync(2)`
+    pub fn sync(&mut self) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        if let Err(e) = self.io().sync() {
+            self.error = e;
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Truncate or extend the file to the length, the position is not changed
+    pub fn truncate(&mut self, len: u64) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        if let Err(e) = self.io().set_len(len) {
             self.error = e;
             return -1;
         }
@@ -255,7 +495,7 @@ impl File {
             }
         }
 
-        let ret = self.fd().seek(w);
+        let ret = self.io().seek(w);
         match ret {
             Ok(n) => {
                 off = n as i64;
@@ -281,13 +521,207 @@ impl File {
         return self.seek(0, SEEK_CUR);
     }
 
+    binary_methods! {
+        read_u8, write_u8, u8, from_le_bytes, to_le_bytes, "a u8";
+        read_i8, write_i8, i8, from_le_bytes, to_le_bytes, "an i8";
+        read_u16_le, write_u16_le, u16, from_le_bytes, to_le_bytes, "a little-endian u16";
+        read_u16_be, write_u16_be, u16, from_be_bytes, to_be_bytes, "a big-endian u16";
+        read_i16_le, write_i16_le, i16, from_le_bytes, to_le_bytes, "a little-endian i16";
+        read_i16_be, write_i16_be, i16, from_be_bytes, to_be_bytes, "a big-endian i16";
+        read_u32_le, write_u32_le, u32, from_le_bytes, to_le_bytes, "a little-endian u32";
+        read_u32_be, write_u32_be, u32, from_be_bytes, to_be_bytes, "a big-endian u32";
+        read_i32_le, write_i32_le, i32, from_le_bytes, to_le_bytes, "a little-endian i32";
+        read_i32_be, write_i32_be, i32, from_be_bytes, to_be_bytes, "a big-endian i32";
+        read_u64_le, write_u64_le, u64, from_le_bytes, to_le_bytes, "a little-endian u64";
+        read_u64_be, write_u64_be, u64, from_be_bytes, to_be_bytes, "a big-endian u64";
+        read_i64_le, write_i64_le, i64, from_le_bytes, to_le_bytes, "a little-endian i64";
+        read_i64_be, write_i64_be, i64, from_be_bytes, to_be_bytes, "a big-endian i64";
+        read_f32_le, write_f32_le, f32, from_le_bytes, to_le_bytes, "a little-endian f32";
+        read_f32_be, write_f32_be, f32, from_be_bytes, to_be_bytes, "a big-endian f32";
+        read_f64_le, write_f64_le, f64, from_le_bytes, to_le_bytes, "a little-endian f64";
+        read_f64_be, write_f64_be, f64, from_be_bytes, to_be_bytes, "a big-endian f64";
+    }
+
+    /// Fill the whole buffer, returns 0 on success, -1 on short read or error
+    pub fn read_exact(&mut self, buf: &mut [u8]) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        if let Err(e) = self.io().read_exact(buf) {
+            self.error = e;
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Write the whole buffer regardless of [`O_NONBLOCK`], returns 0 on success, -1 on short write or error
+    pub fn write_exact<Buffer: AsRef<[u8]>>(&mut self, data: Buffer) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        if let Err(e) = self.io().write_all(data.as_ref()) {
+            self.error = e;
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Read bytes prefixed with a little-endian u32 length, returns 0 on success, -1 on short read or error
+    pub fn read_bytes(&mut self, data: &mut Vec<u8>) -> int {
+        use std::io::Read;
+
+        let mut n = 0;
+        if self.read_u32_le(&mut n) != 0 {
+            return -1;
+        }
+
+        // a corrupt length fails by the short read, without allocating it first
+        let mut buf = Vec::new();
+        if let Err(e) = self.io().take(n as u64).read_to_end(&mut buf) {
+            self.error = e;
+            return -1;
+        }
+
+        if buf.len() != n as usize {
+            self.error = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "failed to fill whole buffer");
+            return -1;
+        }
+
+        *data = buf;
+        return 0;
+    }
+
+    /// Write bytes prefixed with a little-endian u32 length, returns 0 on success, -1 on short write or error
+    pub fn write_bytes<Buffer: AsRef<[u8]>>(&mut self, data: Buffer) -> int {
+        let buf = data.as_ref();
+        if buf.len() > u32::MAX as usize {
+            self.error = std::io::Error::new(std::io::ErrorKind::InvalidInput, "data too long");
+            return -1;
+        }
+
+        if self.write_u32_le(buf.len() as u32) != 0 {
+            return -1;
+        }
+
+        return self.write_exact(buf);
+    }
+
+    /// Read a UTF-8 string written by [`File::write_string`], returns 0 on success, -1 on short read or error
+    pub fn read_string(&mut self, s: &mut String) -> int {
+        let mut buf = Vec::new();
+        if self.read_bytes(&mut buf) != 0 {
+            return -1;
+        }
+
+        match String::from_utf8(buf) {
+            Ok(text) => {
+                *s = text;
+            }
+            Err(e) => {
+                self.error = std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+                return -1;
+            }
+        }
+
+        return 0;
+    }
+
+    /// Write a string prefixed with a little-endian u32 length, returns 0 on success, -1 on short write or error
+    pub fn write_string<T: AsRef<str>>(&mut self, s: T) -> int {
+        return self.write_bytes(s.as_ref());
+    }
+
+    /// Returns an independent File of the same opened file, with the same flags and path.
+    ///
+    /// The file is not reopened, it stays valid even if the path was renamed or removed.
+    /// Returns none on error, check the [`File::error`].
+    pub fn dup(&mut self) -> Option<File> {
+        if self.is_none() {
+            return None;
+        }
+
+        match self.io().try_clone() {
+            Ok(pod) => {
+                let f = File {
+                    pod: Some(pod),
+                    path: self.path.clone(),
+                    flags: self.flags,
+                    error: std::io::Error::new(std::io::ErrorKind::Other, ""),
+                };
+                return Some(f);
+            }
+            Err(e) => {
+                self.error = e;
+            }
+        }
+
+        return None;
+    }
+
+    /// Read data at the offset into buffer, the position is not changed
+    pub fn read_at(&mut self, buf: &mut [u8], offset: u64) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        match self.io().read_at(buf, offset) {
+            Ok(n) => {
+                return n as i32;
+            }
+            Err(e) => {
+                self.error = e;
+            }
+        }
+
+        return -1;
+    }
+
+    /// Write all data at the offset, the position is not changed. Returns the number of bytes written.
+    pub fn write_at<Buffer: AsRef<[u8]>>(&mut self, data: Buffer, offset: u64) -> int {
+        let buf = data.as_ref();
+        let mut i = 0;
+
+        if self.is_none() {
+            return -1;
+        }
+
+        while i < buf.len() {
+            match self.io().write_at(&buf[i..], offset + i as u64) {
+                Ok(n) => i += n,
+                Err(e) => {
+                    self.error = e;
+                    break;
+                }
+            }
+        }
+
+        return i as i32;
+    }
+
     /// Check if inner file descriptor is none
     pub fn is_none(&self) -> bool {
         return self.pod.is_none();
     }
 
     /// Returns a reference to inner file descriptor
+    ///
+    /// Panics if the file was not opened from the disk, see [`Backend`]
     pub fn fd(&mut self) -> Box<&mut std::fs::File> {
-        return Box::new(self.pod.as_mut().unwrap());
+        let f = self.io().file().expect("not a disk file");
+        return Box::new(f);
+    }
+
+    // move the opened handle out, the file is closed
+    pub(crate) fn take_handle(&mut self) -> Option<Box<dyn Handle>> {
+        return self.pod.take();
+    }
+
+    // the opened handle of any backend
+    fn io(&mut self) -> &mut dyn Handle {
+        return self.pod.as_mut().unwrap().as_mut();
     }
 }
@@ -0,0 +1,257 @@
+//! Async variant of [`File`] on tokio, enabled by the `async` feature
+use super::*;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// Intuitive async File, with the same open flags and conventions of [`File`]
+///
+/// It always opens the file from the disk, the [`Backend`] of the thread is not used.
+///
+/// The writes are buffered by tokio, a write error may be reported by a later call only, so
+/// check the result of [`AsyncFile::close`], the errors are lost if the file was simply dropped.
+///
+/// ```rust,no_run
+/// use wsd::fs::*;
+///
+/// async fn test() -> i32 {
+///     let mut f = AsyncFile::new();
+///     if f.open("test.txt", O_CREATE | O_RW).await != 0 {
+///         println!("Error: {}", f.error());
+///         return -1;
+///     }
+///
+///     f.write("Hello World!").await;
+///     f.rewind().await;
+///
+///     let mut buf = [0; 4096];
+///     let n = f.read(&mut buf).await;
+///     if n > 0 {
+///         // success to read n bytes
+///     }
+///
+///     if f.close().await != 0 {
+///         println!("Error: {}", f.error());
+///         return -1;
+///     }
+///     return 0;
+/// }
+/// ```
+pub struct AsyncFile {
+    pod: Option<tokio::fs::File>,
+    path: String,
+    flags: u32,
+    error: std::io::Error,
+}
+
+impl AsyncFile {
+    /// Returns a new AsyncFile instance
+    pub fn new() -> Self {
+        return AsyncFile {
+            pod: None,
+            path: "".to_string(),
+            flags: 0,
+            error: std::io::Error::new(std::io::ErrorKind::Other, ""),
+        };
+    }
+
+    /// Open a file with given flags
+    pub async fn open<T: AsRef<str>>(&mut self, path: T, flags: u32) -> int {
+        let mut options = tokio::fs::OpenOptions::new();
+
+        self.pod = None;
+        self.path = path.as_ref().to_string();
+
+        self.flags = flags;
+        if flags == 0 {
+            self.flags |= O_READ;
+        }
+
+        options.create(self.flags & O_CREATE != 0);
+        options.append(self.flags & O_APPEND != 0);
+        options.read(self.flags & O_READ != 0);
+        options.write(self.flags & O_WRITE != 0);
+        options.truncate(self.flags & O_TRUNCATE != 0);
+
+        let ret = options.open(path.as_ref()).await;
+        match ret {
+            Ok(f) => {
+                self.pod = Some(f);
+            }
+            Err(e) => {
+                self.error = e;
+                return -1;
+            }
+        }
+
+        return 0;
+    }
+
+    /// Flush the pending writes and drop the inner file descriptor, returns 0 on success,
+    /// -1 if the file was not opened or the pending writes were failed
+    pub async fn close(&mut self) -> int {
+        if self.flush().await != 0 {
+            self.pod = None;
+            return -1;
+        }
+
+        self.pod = None;
+        return 0;
+    }
+
+    /// Returns the path of the file
+    pub fn path(&self) -> &String {
+        return &self.path;
+    }
+
+    /// Returns the last error of calls
+    pub fn error(&self) -> &std::io::Error {
+        return &self.error;
+    }
+
+    /// Write all data unless [`O_NONBLOCK`] flag was set, the errors of the buffered data are
+    /// reported by a later call, [`AsyncFile::flush`] or [`AsyncFile::close`]
+    pub async fn write<Buffer: AsRef<[u8]>>(&mut self, data: Buffer) -> int {
+        let mut i = 0;
+        let buf = data.as_ref();
+        let n = buf.len() as i32;
+
+        if self.is_none() {
+            return -1;
+        }
+
+        let nb = self.flags & O_NONBLOCK != 0;
+        let fd = self.pod.as_mut().unwrap();
+
+        while i < n {
+            let off = i as usize;
+            let ret = fd.write(&buf[off..]).await;
+            match ret {
+                Ok(n) => i += n as i32,
+                Err(e) => {
+                    self.error = e;
+                    break;
+                }
+            }
+            if nb {
+                break;
+            }
+        }
+
+        return i;
+    }
+
+    /// Read data into buffer
+    pub async fn read(&mut self, buf: &mut [u8]) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        let mut i = 0;
+        let fd = self.pod.as_mut().unwrap();
+
+        let ret = fd.read(buf).await;
+        match ret {
+            Ok(n) => {
+                i = n as i32;
+            }
+            Err(e) => {
+                self.error = e;
+            }
+        }
+
+        return i;
+    }
+
+    /// Read all data until the end
+    pub async fn read_to_end(&mut self, buf: &mut Vec<u8>) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        match self.pod.as_mut().unwrap().read_to_end(buf).await {
+            Ok(n) => {
+                return n as i32;
+            }
+            Err(e) => {
+                self.error = e;
+            }
+        }
+
+        return -1;
+    }
+
+    /// Flush the file
+    pub async fn flush(&mut self) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        if let Err(e) = self.pod.as_mut().unwrap().flush().await {
+            self.error = e;
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Seek to a position
+    /// * `offset` - relative position
+    /// * `whence` - One of: [`SEEK_SET`], [`SEEK_CUR`], [`SEEK_END`]
+    pub async fn seek(&mut self, offset: i64, whence: int) -> i64 {
+        if self.is_none() {
+            return -1;
+        }
+
+        let w;
+        let mut off = -1;
+
+        match whence {
+            SEEK_SET => {
+                w = std::io::SeekFrom::Start(offset as u64);
+            }
+            SEEK_CUR => {
+                w = std::io::SeekFrom::Current(offset);
+            }
+            SEEK_END => {
+                w = std::io::SeekFrom::End(offset);
+            }
+            _ => {
+                return off;
+            }
+        }
+
+        let ret = self.pod.as_mut().unwrap().seek(w).await;
+        match ret {
+            Ok(n) => {
+                off = n as i64;
+            }
+            Err(e) => {
+                self.error = e;
+            }
+        }
+
+        return off;
+    }
+
+    /// Reset the position
+    pub async fn rewind(&mut self) -> int {
+        if self.seek(0, SEEK_SET).await < 0 {
+            return -1;
+        }
+        return 0;
+    }
+
+    /// Returns the current position
+    pub async fn position(&mut self) -> i64 {
+        return self.seek(0, SEEK_CUR).await;
+    }
+
+    /// Check if inner file descriptor is none
+    pub fn is_none(&self) -> bool {
+        return self.pod.is_none();
+    }
+
+    /// Returns a reference to inner file descriptor
+    pub fn fd(&mut self) -> &mut tokio::fs::File {
+        return self.pod.as_mut().unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+//! Pluggable storage behind [`File`](super::File), [`mkdir`](super::mkdir) and [`remove`](super::remove)
+use super::*;
+use std::cell::RefCell;
+use std::io::{Read, Seek, Write};
+use std::sync::Arc;
+
+/// An opened file of a [`Backend`]
+pub trait Handle: Read + Write + Seek + Send {
+    /// Returns the underlying OS file, none if the handle is not backed by the disk
+    fn file(&mut self) -> Option<&mut std::fs::File> {
+        return None;
+    }
+
+    /// Returns a new handle of the same opened file, the position is shared like `dup(2)`
+    fn try_clone(&self) -> std::io::Result<Box<dyn Handle>> {
+        return Err(unsupported());
+    }
+
+    /// Read at the offset without changing the position
+    fn read_at(&mut self, _buf: &mut [u8], _offset: u64) -> std::io::Result<usize> {
+        return Err(unsupported());
+    }
+
+    /// Write at the offset without changing the position
+    fn write_at(&mut self, _buf: &[u8], _offset: u64) -> std::io::Result<usize> {
+        return Err(unsupported());
+    }
+
+    /// Flush the data and metadata to the storage
+    fn sync(&mut self) -> std::io::Result<()> {
+        return self.flush();
+    }
+
+    /// Truncate or extend the file
+    fn set_len(&mut self, _len: u64) -> std::io::Result<()> {
+        return Err(unsupported());
+    }
+}
+
+fn unsupported() -> std::io::Error {
+    return std::io::Error::new(std::io::ErrorKind::Unsupported, "not supported by the backend");
+}
+
+impl Handle for std::fs::File {
+    fn file(&mut self) -> Option<&mut std::fs::File> {
+        return Some(self);
+    }
+
+    fn try_clone(&self) -> std::io::Result<Box<dyn Handle>> {
+        let f = std::fs::File::try_clone(self)?;
+        return Ok(Box::new(f));
+    }
+
+    #[cfg(unix)]
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        return std::os::unix::fs::FileExt::read_at(self, buf, offset);
+    }
+
+    #[cfg(unix)]
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+        return std::os::unix::fs::FileExt::write_at(self, buf, offset);
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        return self.sync_all();
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        return std::fs::File::set_len(self, len);
+    }
+}
+
+/// Storage used by the file system module, install one with [`set_backend`] or [`scoped_backend`]
+pub trait Backend: Send + Sync {
+    /// Open a file with the flags of [`File::open`]
+    fn open(&self, path: &Path, flags: u32) -> std::io::Result<Box<dyn Handle>>;
+    /// Create directories recursively
+    fn mkdir(&self, path: &Path) -> std::io::Result<()>;
+    /// Remove a file
+    fn remove(&self, path: &Path) -> std::io::Result<()>;
+    /// Rename a file, replacing the destination
+    fn rename(&self, _from: &Path, _to: &Path) -> std::io::Result<()> {
+        return Err(unsupported());
+    }
+}
+
+/// The real file system, used unless another backend was installed
+pub struct Disk;
+
+impl Backend for Disk {
+    fn open(&self, path: &Path, flags: u32) -> std::io::Result<Box<dyn Handle>> {
+        let mut options = std::fs::File::options();
+        options.create(flags & O_CREATE != 0);
+        options.append(flags & O_APPEND != 0);
+        options.read(flags & O_READ != 0);
+        options.write(flags & O_WRITE != 0);
+        options.truncate(flags & O_TRUNCATE != 0);
+
+        let f = options.open(path)?;
+        return Ok(Box::new(f));
+    }
+
+    fn mkdir(&self, path: &Path) -> std::io::Result<()> {
+        return std::fs::create_dir_all(path);
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        return std::fs::remove_file(path);
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        return std::fs::rename(from, to);
+    }
+}
+
+thread_local! {
+    static BACKEND: RefCell<Option<Arc<dyn Backend>>> = RefCell::new(None);
+}
+
+/// Install a backend for the current thread, none to restore the disk
+pub fn set_backend(backend: Option<Arc<dyn Backend>>) {
+    BACKEND.with(|b| *b.borrow_mut() = backend);
+}
+
+/// Install a backend for the current thread until the returned guard was dropped
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use wsd::fs::*;
+///
+/// fn test() {
+///     let memory = Arc::new(MemoryFs::new());
+///     let _guard = scoped_backend(memory.clone());
+///
+///     let mut f = File::new();
+///     f.open("test.txt", O_CREATE | O_RW);
+///     f.write("never touches the disk");
+/// }
+/// ```
+pub fn scoped_backend(backend: Arc<dyn Backend>) -> ScopedBackend {
+    let previous = BACKEND.with(|b| b.borrow_mut().replace(backend));
+    return ScopedBackend { previous };
+}
+
+/// Restores the previous backend on drop, see [`scoped_backend`]
+pub struct ScopedBackend {
+    previous: Option<Arc<dyn Backend>>,
+}
+
+impl Drop for ScopedBackend {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        BACKEND.with(|b| *b.borrow_mut() = previous);
+    }
+}
+
+// the backend of current thread
+pub(crate) fn current() -> Arc<dyn Backend> {
+    let installed = BACKEND.with(|b| b.borrow().clone());
+    return installed.unwrap_or_else(|| Arc::new(Disk));
+}
@@ -0,0 +1,152 @@
+//! Chunked iteration and splitting of large files
+use super::*;
+use std::io::ErrorKind;
+use std::ops::Range;
+
+/// Reads a file in fixed-size blocks, one buffer is reused for all blocks, see [`File::chunks`]
+pub struct Chunks<'a> {
+    file: &'a mut File,
+    buf: Vec<u8>,
+    failed: bool,
+}
+
+impl<'a> Chunks<'a> {
+    /// Returns the next block, the last one may be shorter, none at the end or on error
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&[u8]> {
+        if self.failed || self.file.is_none() {
+            return None;
+        }
+
+        let mut n = 0;
+        while n < self.buf.len() {
+            match self.file.io().read(&mut self.buf[n..]) {
+                Ok(0) => break,
+                Ok(i) => n += i,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.file.error = e;
+                    self.failed = true;
+                    return None;
+                }
+            }
+        }
+
+        if n == 0 {
+            return None;
+        }
+
+        return Some(&self.buf[..n]);
+    }
+
+    /// Returns the error if the iteration was stopped by an error
+    pub fn error(&self) -> Option<&std::io::Error> {
+        if self.failed {
+            return Some(&self.file.error);
+        }
+        return None;
+    }
+}
+
+impl File {
+    /// Iterate over fixed-size blocks from the current position
+    ///
+    /// ```rust,no_run
+    /// use wsd::fs::*;
+    ///
+    /// fn test() {
+    ///     let mut f = File::new();
+    ///     f.open("big.bin", O_READ);
+    ///
+    ///     let mut chunks = f.chunks(1 << 20);
+    ///     while let Some(block) = chunks.next() {
+    ///         println!("{} bytes", block.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn chunks(&mut self, size: usize) -> Chunks<'_> {
+        return Chunks {
+            file: self,
+            buf: vec![0; size.max(1)],
+            failed: false,
+        };
+    }
+
+    /// Split the file into at most `n` byte ranges for parallel workers, each range ends after a
+    /// newline or at the end of file. Returns an empty vector if the file is empty or on error.
+    pub fn split(&mut self, n: usize) -> Vec<Range<u64>> {
+        return self.split_by(n, b'\n');
+    }
+
+    /// Split like [`File::split`] with a custom record delimiter, the position is kept
+    pub fn split_by(&mut self, n: usize, delimiter: u8) -> Vec<Range<u64>> {
+        let mut ranges = Vec::new();
+        let pos = self.position();
+        let len = self.seek(0, SEEK_END);
+        if pos < 0 || len <= 0 {
+            return ranges;
+        }
+
+        let len = len as u64;
+        let n = n.max(1) as u64;
+        let mut start = 0;
+
+        for i in 1..=n {
+            if start >= len {
+                break;
+            }
+
+            let mut end = len;
+            let target = len * i / n;
+            if i < n && target > start {
+                // the boundary is after the delimiter at or following the target
+                match self.find(target - 1, delimiter) {
+                    Some(off) => end = off + 1,
+                    None => {
+                        if self.error.kind() != ErrorKind::UnexpectedEof {
+                            ranges.clear();
+                            break;
+                        }
+                    }
+                }
+            } else if i < n {
+                continue;
+            }
+
+            ranges.push(start..end);
+            start = end;
+        }
+
+        self.seek(pos, SEEK_SET);
+        return ranges;
+    }
+
+    // offset of the delimiter at or after the position, UnexpectedEof error if not found
+    fn find(&mut self, from: u64, delimiter: u8) -> Option<u64> {
+        if self.seek(from as i64, SEEK_SET) < 0 {
+            return None;
+        }
+
+        let mut off = from;
+        let mut buf = [0; 4096];
+        loop {
+            let n = match self.io().read(&mut buf) {
+                Ok(0) => {
+                    self.error = std::io::Error::new(ErrorKind::UnexpectedEof, "delimiter not found");
+                    return None;
+                }
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.error = e;
+                    return None;
+                }
+            };
+
+            if let Some(i) = buf[..n].iter().position(|c| *c == delimiter) {
+                return Some(off + i as u64);
+            }
+            off += n as u64;
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Zero-copy file transfer, using reflink, `copy_file_range(2)` or `sendfile(2)` when available
+use super::*;
+use std::io::ErrorKind;
+
+// bytes per system call, also the granularity of the progress
+const CHUNK: u64 = 8 << 20;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Method {
+    CopyFileRange,
+    SendFile,
+    Buffered,
+}
+
+/// Copy a file, the permissions are copied too. Returns the number of bytes copied, -1 on error, see [`last_error`]
+pub fn copy<S: AsRef<Path>, D: AsRef<Path>>(src: S, dst: D) -> i64 {
+    return copy_progress(src, dst, |_, _| {});
+}
+
+/// Copy a file like [`copy`], the callback receives the bytes copied and the total length
+///
+/// ```rust,no_run
+/// fn test() {
+///     wsd::fs::copy_progress("image.iso", "backup.iso", |copied, total| {
+///         println!("{}/{}", copied, total);
+///     });
+/// }
+/// ```
+pub fn copy_progress<S, D, F>(src: S, dst: D, mut f: F) -> i64
+where
+    S: AsRef<Path>,
+    D: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    let mut input = File::new();
+    if input.open_path(src.as_ref(), O_READ) != 0 {
+        return fail(input.error) as i64;
+    }
+
+    let mut output = File::new();
+    if output.open_path(dst.as_ref(), O_CREATE | O_TRUNCATE | O_WRITE) != 0 {
+        return fail(output.error) as i64;
+    }
+
+    // share the blocks on copy-on-write file systems
+    let n = match reflink(&mut input, &mut output) {
+        Some(n) => {
+            f(n, n);
+            n as i64
+        }
+        None => input.copy_to_progress(&mut output, -1, f),
+    };
+
+    if n < 0 {
+        return fail(input.error) as i64;
+    }
+
+    // the permissions last, the data was written through the handle
+    if let Err(e) = copy_permissions(&mut input, &mut output) {
+        return fail(e) as i64;
+    }
+
+    return n;
+}
+
+fn copy_permissions(input: &mut File, output: &mut File) -> std::io::Result<()> {
+    if let (Some(a), Some(b)) = (input.io().file(), output.pod.as_mut().and_then(|p| p.file())) {
+        b.set_permissions(a.metadata()?.permissions())?;
+    }
+    return Ok(());
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(input: &mut File, output: &mut File) -> Option<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let src = input.io().file()?;
+    let len = src.metadata().ok()?.len();
+    let fd = src.as_raw_fd();
+    let dst = output.io().file()?;
+    if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, fd) } != 0 {
+        return None;
+    }
+    return Some(len);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_input: &mut File, _output: &mut File) -> Option<u64> {
+    return None;
+}
+
+impl File {
+    /// Copy `len` bytes from the current position into another file at its position, negative
+    /// `len` to copy until the end. Returns the number of bytes copied, -1 on error.
+    pub fn copy_to(&mut self, dst: &mut File, len: i64) -> i64 {
+        return self.copy_to_progress(dst, len, |_, _| {});
+    }
+
+    /// Copy like [`File::copy_to`], the callback receives the bytes copied and the total length.
+    ///
+    /// The data doesn't pass through userspace unless neither `copy_file_range(2)` nor `sendfile(2)`
+    /// supports the files. The error is recorded into this file.
+    pub fn copy_to_progress<F: FnMut(u64, u64)>(&mut self, dst: &mut File, len: i64, mut f: F) -> i64 {
+        if self.is_none() || dst.is_none() {
+            return -1;
+        }
+
+        let total = if len >= 0 {
+            len as u64
+        } else {
+            let pos = self.position();
+            let end = self.seek(0, SEEK_END);
+            if pos < 0 || end < 0 || self.seek(pos, SEEK_SET) < 0 {
+                return -1;
+            }
+            (end - pos) as u64
+        };
+
+        let mut method = if cfg!(target_os = "linux") { Method::CopyFileRange } else { Method::Buffered };
+        let mut buf = Vec::new();
+        let mut done = 0;
+
+        while done < total {
+            let chunk = (total - done).min(CHUNK) as usize;
+            let ret = match method {
+                Method::CopyFileRange | Method::SendFile => kernel_copy(self, dst, chunk, method),
+                Method::Buffered => {
+                    buf.resize(chunk.min(64 << 10), 0);
+                    buffered_copy(self, dst, &mut buf)
+                }
+            };
+
+            match ret {
+                Ok(0) => {
+                    break;
+                }
+                Ok(n) => {
+                    done += n as u64;
+                    f(done, total);
+                }
+                Err(e) if method != Method::Buffered && unsupported(&e) => {
+                    // fall back to the next method, the offsets are still consistent
+                    method = if method == Method::CopyFileRange { Method::SendFile } else { Method::Buffered };
+                }
+                Err(e) => {
+                    self.error = e;
+                    return -1;
+                }
+            }
+        }
+
+        return done as i64;
+    }
+}
+
+// the method was not supported by the kernel or the files
+fn unsupported(e: &std::io::Error) -> bool {
+    if e.kind() == ErrorKind::Unsupported {
+        return true;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let codes = [libc::ENOSYS, libc::EXDEV, libc::EINVAL, libc::EOPNOTSUPP, libc::EBADF, libc::ETXTBSY];
+        if let Some(code) = e.raw_os_error() {
+            return codes.contains(&code);
+        }
+    }
+
+    return false;
+}
+
+#[cfg(target_os = "linux")]
+fn kernel_copy(src: &mut File, dst: &mut File, len: usize, method: Method) -> std::io::Result<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    let unsupported = || std::io::Error::new(ErrorKind::Unsupported, "not a disk file");
+    let input = src.io().file().ok_or_else(unsupported)?.as_raw_fd();
+    let output = dst.io().file().ok_or_else(unsupported)?.as_raw_fd();
+
+    // both advance the file offsets
+    let n = unsafe {
+        if method == Method::CopyFileRange {
+            libc::copy_file_range(input, std::ptr::null_mut(), output, std::ptr::null_mut(), len, 0)
+        } else {
+            libc::sendfile(output, input, std::ptr::null_mut(), len)
+        }
+    };
+
+    if n < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    return Ok(n as usize);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kernel_copy(_src: &mut File, _dst: &mut File, _len: usize, _method: Method) -> std::io::Result<usize> {
+    return Err(std::io::Error::new(ErrorKind::Unsupported, "not supported"));
+}
+
+fn buffered_copy(src: &mut File, dst: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let n = src.io().read(buf)?;
+    dst.io().write_all(&buf[..n])?;
+    return Ok(n);
+}
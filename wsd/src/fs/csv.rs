@@ -0,0 +1,360 @@
+//! CSV reader and writer on top of [`File`], quoting follows RFC 4180
+//!
+//! Rows could be mapped into serde structs, including the structs declared with `json!{}`.
+//!
+//! ```rust,no_run
+//! use serde::{Deserialize, Serialize};
+//! use wsd::fs::*;
+//! use wsd::json::*;
+//!
+//! json!{ Student { name: String, age: u16 } }
+//!
+//! fn test() {
+//!     let mut w = csv::Writer::new();
+//!     w.open("students.csv", O_CREATE | O_TRUNCATE | O_WRITE);
+//!     w.serialize(&Student { name: "John".to_string(), age: 18 });
+//!     w.close();
+//!
+//!     let mut r = csv::Reader::new();
+//!     if r.open("students.csv") != 0 {
+//!         println!("Error: {}", r.error());
+//!         return;
+//!     }
+//!
+//!     let mut student = Student::new();
+//!     while r.deserialize(&mut student) > 0 {
+//!         println!("{:?}", student);
+//!     }
+//! }
+//! ```
+use super::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Adapts the File to std::io
+struct Stream(File);
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0.is_none() {
+            return Err(not_opened());
+        }
+        return self.0.io().read(buf);
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.0.is_none() {
+            return Err(not_opened());
+        }
+        return self.0.io().write(buf);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.0.is_none() {
+            return Ok(());
+        }
+        return self.0.io().flush();
+    }
+}
+
+fn not_opened() -> Error {
+    return Error::new(ErrorKind::NotConnected, "file not opened");
+}
+
+/// CSV reader
+pub struct Reader {
+    inner: Option<::csv::Reader<Stream>>,
+    delimiter: u8,
+    quote: u8,
+    has_headers: bool,
+    headers: Vec<String>,
+    record: ::csv::StringRecord,
+    error: Error,
+}
+
+impl Reader {
+    /// Returns a new reader, comma delimited with a header row
+    pub fn new() -> Self {
+        return Self {
+            inner: None,
+            delimiter: b',',
+            quote: b'"',
+            has_headers: true,
+            headers: Vec::new(),
+            record: ::csv::StringRecord::new(),
+            error: Error::new(ErrorKind::Other, ""),
+        };
+    }
+
+    /// Set the field delimiter before open
+    pub fn delimiter(&mut self, delimiter: u8) -> &mut Self {
+        self.delimiter = delimiter;
+        return self;
+    }
+
+    /// Set the quote character before open
+    pub fn quote(&mut self, quote: u8) -> &mut Self {
+        self.quote = quote;
+        return self;
+    }
+
+    /// Whether the first row is the header, it's required to deserialize into structs
+    pub fn has_headers(&mut self, yes: bool) -> &mut Self {
+        self.has_headers = yes;
+        return self;
+    }
+
+    /// Open a CSV file for reading
+    pub fn open<T: AsRef<str>>(&mut self, path: T) -> int {
+        let mut f = File::new();
+        if f.open(path, O_READ) != 0 {
+            self.error = std::mem::replace(&mut f.error, Error::new(ErrorKind::Other, ""));
+            return -1;
+        }
+        return self.attach(f);
+    }
+
+    /// Read from an opened file, starting at its current position
+    pub fn attach(&mut self, f: File) -> int {
+        let mut inner = ::csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .from_reader(Stream(f));
+
+        self.headers.clear();
+        if self.has_headers {
+            match inner.headers() {
+                Ok(h) => {
+                    self.headers = h.iter().map(String::from).collect();
+                }
+                Err(e) => {
+                    self.error = e.into();
+                    return -1;
+                }
+            }
+        }
+
+        self.inner = Some(inner);
+        return 0;
+    }
+
+    /// Returns the header row, empty if no headers
+    pub fn headers(&self) -> &Vec<String> {
+        return &self.headers;
+    }
+
+    /// Read next row, returns the number of fields, 0 at the end, -1 on error
+    pub fn read(&mut self, row: &mut Vec<String>) -> int {
+        let ret = self.next();
+        if ret <= 0 {
+            return ret;
+        }
+
+        row.clear();
+        row.extend(self.record.iter().map(String::from));
+        return row.len() as int;
+    }
+
+    /// Read next row into a serde struct by the headers, returns the number of fields, 0 at the end, -1 on error
+    pub fn deserialize<T: DeserializeOwned>(&mut self, value: &mut T) -> int {
+        let ret = self.next();
+        if ret <= 0 {
+            return ret;
+        }
+
+        let headers = if self.has_headers {
+            Some(::csv::StringRecord::from(self.headers.clone()))
+        } else {
+            None
+        };
+
+        match self.record.deserialize(headers.as_ref()) {
+            Ok(v) => {
+                *value = v;
+            }
+            Err(e) => {
+                self.error = e.into();
+                return -1;
+            }
+        }
+
+        return self.record.len() as int;
+    }
+
+    // read a record, 1 on success, 0 at the end, -1 on error
+    fn next(&mut self) -> int {
+        let inner = match self.inner.as_mut() {
+            Some(r) => r,
+            None => {
+                self.error = not_opened();
+                return -1;
+            }
+        };
+
+        match inner.read_record(&mut self.record) {
+            Ok(true) => {
+                return 1;
+            }
+            Ok(false) => {
+                return 0;
+            }
+            Err(e) => {
+                self.error = e.into();
+                return -1;
+            }
+        }
+    }
+
+    /// Close the file
+    pub fn close(&mut self) {
+        self.inner = None;
+    }
+
+    /// Returns the last error of calls
+    pub fn error(&self) -> &Error {
+        return &self.error;
+    }
+}
+
+/// CSV writer
+pub struct Writer {
+    inner: Option<::csv::Writer<Stream>>,
+    delimiter: u8,
+    quote: u8,
+    has_headers: bool,
+    error: Error,
+}
+
+impl Writer {
+    /// Returns a new writer, comma delimited, the headers are written from the first serialized struct
+    pub fn new() -> Self {
+        return Self {
+            inner: None,
+            delimiter: b',',
+            quote: b'"',
+            has_headers: true,
+            error: Error::new(ErrorKind::Other, ""),
+        };
+    }
+
+    /// Set the field delimiter before open
+    pub fn delimiter(&mut self, delimiter: u8) -> &mut Self {
+        self.delimiter = delimiter;
+        return self;
+    }
+
+    /// Set the quote character before open
+    pub fn quote(&mut self, quote: u8) -> &mut Self {
+        self.quote = quote;
+        return self;
+    }
+
+    /// Whether to write the header row from the field names of the first serialized struct
+    pub fn has_headers(&mut self, yes: bool) -> &mut Self {
+        self.has_headers = yes;
+        return self;
+    }
+
+    /// Open a CSV file for writing with the flags of [`File::open`]
+    pub fn open<T: AsRef<str>>(&mut self, path: T, flags: u32) -> int {
+        let mut f = File::new();
+        if f.open(path, flags | O_WRITE) != 0 {
+            self.error = std::mem::replace(&mut f.error, Error::new(ErrorKind::Other, ""));
+            return -1;
+        }
+        return self.attach(f);
+    }
+
+    /// Write to an opened file
+    pub fn attach(&mut self, f: File) -> int {
+        let inner = ::csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .from_writer(Stream(f));
+        self.inner = Some(inner);
+        return 0;
+    }
+
+    /// Write a row, fields are quoted when needed, returns 0 on success, -1 on error
+    pub fn write<I, T>(&mut self, row: I) -> int
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let inner = match self.inner.as_mut() {
+            Some(w) => w,
+            None => {
+                self.error = not_opened();
+                return -1;
+            }
+        };
+
+        if let Err(e) = inner.write_record(row) {
+            self.error = e.into();
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Write a serde struct as a row, returns 0 on success, -1 on error
+    pub fn serialize<T: Serialize>(&mut self, value: &T) -> int {
+        let inner = match self.inner.as_mut() {
+            Some(w) => w,
+            None => {
+                self.error = not_opened();
+                return -1;
+            }
+        };
+
+        if let Err(e) = inner.serialize(value) {
+            self.error = e.into();
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Flush the buffered rows into the file
+    pub fn flush(&mut self) -> int {
+        let inner = match self.inner.as_mut() {
+            Some(w) => w,
+            None => {
+                self.error = not_opened();
+                return -1;
+            }
+        };
+
+        if let Err(e) = inner.flush() {
+            self.error = e;
+            return -1;
+        }
+
+        return 0;
+    }
+
+    /// Flush and close the file
+    pub fn close(&mut self) -> int {
+        let ret = if self.inner.is_some() { self.flush() } else { 0 };
+        self.inner = None;
+        return ret;
+    }
+
+    /// Returns the last error of calls
+    pub fn error(&self) -> &Error {
+        return &self.error;
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
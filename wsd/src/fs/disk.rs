@@ -0,0 +1,102 @@
+//! File system capacity and disk usage
+use super::*;
+use std::collections::HashSet;
+use std::os::unix::fs::MetadataExt;
+
+/// Capacity of a mounted file system, see [`statvfs`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FsStat {
+    /// Total size as bytes
+    pub total: u64,
+    /// Free bytes, including the blocks reserved for root
+    pub free: u64,
+    /// Bytes available to unprivileged users
+    pub available: u64,
+    /// Total number of inodes
+    pub inodes: u64,
+    /// Free inodes
+    pub inodes_free: u64,
+    /// Inodes available to unprivileged users
+    pub inodes_available: u64,
+}
+
+/// Sizes of a directory tree, see [`du`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Sum of the file lengths
+    pub apparent: u64,
+    /// Sum of the allocated blocks as bytes
+    pub disk: u64,
+    /// Number of files, directories and links
+    pub files: u64,
+}
+
+/// Query the capacity of the file system containing the path, returns 0 on success, -1 on error, see [`last_error`]
+///
+/// ```rust,no_run
+/// use wsd::fs::*;
+///
+/// fn test() {
+///     let mut stat = FsStat::default();
+///     if statvfs("/tmp", &mut stat) == 0 && stat.available < 1 << 30 {
+///         println!("less than 1 GB available");
+///     }
+/// }
+/// ```
+pub fn statvfs<P: AsRef<Path>>(path: P, stat: &mut FsStat) -> int {
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = match std::ffi::CString::new(path.as_ref().as_os_str().as_bytes()) {
+        Ok(s) => s,
+        Err(e) => {
+            return fail(e.into());
+        }
+    };
+
+    let mut buf: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(name.as_ptr(), &mut buf) } != 0 {
+        return fail(std::io::Error::last_os_error());
+    }
+
+    let size = buf.f_frsize as u64;
+    stat.total = buf.f_blocks as u64 * size;
+    stat.free = buf.f_bfree as u64 * size;
+    stat.available = buf.f_bavail as u64 * size;
+    stat.inodes = buf.f_files as u64;
+    stat.inodes_free = buf.f_ffree as u64;
+    stat.inodes_available = buf.f_favail as u64;
+
+    return 0;
+}
+
+/// Walk the path and sum the sizes like `du`, symlinks are not followed and hard links are
+/// counted once. Returns 0 on success, -1 on error, see [`last_error`]
+pub fn du<P: AsRef<Path>>(path: P, usage: &mut DiskUsage) -> int {
+    let mut seen = HashSet::new();
+    *usage = DiskUsage::default();
+
+    if let Err(e) = walk(path.as_ref(), usage, &mut seen) {
+        return fail(e);
+    }
+
+    return 0;
+}
+
+fn walk(path: &Path, usage: &mut DiskUsage, seen: &mut HashSet<(u64, u64)>) -> std::io::Result<()> {
+    let meta = std::fs::symlink_metadata(path)?;
+    if meta.nlink() > 1 && !meta.is_dir() && !seen.insert((meta.dev(), meta.ino())) {
+        return Ok(());
+    }
+
+    usage.files += 1;
+    usage.apparent += meta.len();
+    usage.disk += meta.blocks() * 512;
+
+    if meta.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            walk(&entry?.path(), usage, seen)?;
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,200 @@
+//! Follow a growing file like `tail -f`
+use super::*;
+
+impl File {
+    /// Follow the file as it grows like `tail -f`, starting from the current position.
+    ///
+    /// Each appended line is passed to the callback without the trailing newline, return false
+    /// from the callback to stop. Truncation and rotation (the path was replaced by another file)
+    /// are detected, the file will be reopened automatically. Returns 0 when stopped by the callback,
+    /// -1 on error, or if the file is not on the disk.
+    ///
+    /// ```rust,no_run
+    /// use wsd::fs::*;
+    ///
+    /// fn test() {
+    ///     let mut f = File::new();
+    ///     f.open("/var/log/syslog", O_READ);
+    ///     f.seek(0, SEEK_END);
+    ///     f.follow(|line| {
+    ///         println!("{}", line);
+    ///         return !line.contains("shutdown");
+    ///     });
+    /// }
+    /// ```
+    pub fn follow<F: FnMut(&str) -> bool>(&mut self, mut f: F) -> int {
+        if self.is_none() {
+            return -1;
+        }
+
+        // the changes are watched on the disk
+        if self.io().file().is_none() {
+            self.error = std::io::Error::new(std::io::ErrorKind::Unsupported, "not a file on the disk");
+            return -1;
+        }
+
+        let path = self.path.clone();
+        let mut watcher = match Watcher::new(Path::new(&path)) {
+            Ok(w) => w,
+            Err(e) => {
+                self.error = e;
+                return -1;
+            }
+        };
+
+        let mut pending: Vec<u8> = Vec::new();
+        let mut buf = [0; 4096];
+
+        loop {
+            // drain the appended data
+            loop {
+                let n = match self.io().read(&mut buf) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        self.error = e;
+                        return -1;
+                    }
+                };
+
+                if n == 0 {
+                    break;
+                }
+
+                pending.extend_from_slice(&buf[..n]);
+                while let Some(i) = pending.iter().position(|c| *c == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=i).collect();
+                    let mut text = String::from_utf8_lossy(&line[..i]);
+                    if text.ends_with('\r') {
+                        text.to_mut().pop();
+                    }
+                    if !f(&text) {
+                        return 0;
+                    }
+                }
+            }
+
+            // rotated or truncated
+            if let Ok(meta) = std::fs::metadata(&path) {
+                if Some(inode(&meta)) != self.inode() {
+                    if self.open(&path, self.flags & !(O_CREATE | O_TRUNCATE)) != 0 {
+                        return -1;
+                    }
+                    pending.clear();
+                    continue;
+                }
+
+                if (meta.len() as i64) < self.position() {
+                    self.rewind();
+                    pending.clear();
+                    continue;
+                }
+            }
+
+            if let Err(e) = watcher.wait() {
+                self.error = e;
+                return -1;
+            }
+        }
+    }
+
+    // identity of the opened file
+    fn inode(&mut self) -> Option<u64> {
+        let f = self.io().file()?;
+        let meta = f.metadata().ok()?;
+        return Some(inode(&meta));
+    }
+}
+
+#[cfg(unix)]
+fn inode(meta: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    return meta.ino();
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &std::fs::Metadata) -> u64 {
+    return 0;
+}
+
+// Wakes up on changes of the directory which contains the file
+#[cfg(target_os = "linux")]
+struct Watcher {
+    fd: i32,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    fn new(path: &Path) -> std::io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let name = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // close the fd on error
+        let watcher = Watcher { fd };
+        let mask = libc::IN_MODIFY
+            | libc::IN_ATTRIB
+            | libc::IN_CLOSE_WRITE
+            | libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO;
+        if unsafe { libc::inotify_add_watch(fd, name.as_ptr(), mask) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        return Ok(watcher);
+    }
+
+    // block until any event, the timeout guards against missed events
+    fn wait(&mut self) -> std::io::Result<()> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        if unsafe { libc::poll(&mut pfd, 1, 1000) } < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+
+        // discard the events
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+
+        return Ok(());
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    fn new(_path: &Path) -> std::io::Result<Self> {
+        return Ok(Watcher);
+    }
+
+    fn wait(&mut self) -> std::io::Result<()> {
+        std::thread::sleep(std::time::Duration::from_millis(250));
+        return Ok(());
+    }
+}
@@ -0,0 +1,247 @@
+//! Single instance PID files and named mutexes across processes, based on `flock(2)`
+//!
+//! The locks are released by the kernel when the process exits, so a crashed process never leaves a stale lock.
+use super::*;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+// open and lock the file, retry if the path was replaced while waiting
+fn lock(path: &str, blocking: bool) -> std::io::Result<std::fs::File> {
+    loop {
+        let f = std::fs::File::options()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+
+        let op = if blocking { libc::LOCK_EX } else { libc::LOCK_EX | libc::LOCK_NB };
+        if unsafe { libc::flock(f.as_raw_fd(), op) } != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // the holder may have removed the file before we got the lock
+        let own = f.metadata()?;
+        if let Ok(meta) = std::fs::metadata(path) {
+            if meta.ino() == own.ino() && meta.dev() == own.dev() {
+                return Ok(f);
+            }
+        }
+    }
+}
+
+/// PID file to keep a daemon single instance
+///
+/// ```rust,no_run
+/// use wsd::fs::*;
+///
+/// fn main() {
+///     let mut pid = PidFile::new();
+///     if pid.acquire("/run/daemon.pid") != 0 {
+///         println!("Error: {}", pid.error());
+///         return;
+///     }
+///
+///     // the file was removed when the pid dropped
+/// }
+/// ```
+pub struct PidFile {
+    pod: Option<std::fs::File>,
+    path: String,
+    error: Error,
+}
+
+impl PidFile {
+    /// Returns a new PidFile instance
+    pub fn new() -> Self {
+        return Self {
+            pod: None,
+            path: "".to_string(),
+            error: Error::new(ErrorKind::Other, ""),
+        };
+    }
+
+    /// Lock the PID file and write the current PID, returns 0 on success, -1 if another process
+    /// is running or on error. A PID left by a dead process is stale and will be replaced.
+    pub fn acquire<T: AsRef<str>>(&mut self, path: T) -> int {
+        self.release();
+        self.path = path.as_ref().to_string();
+
+        let mut f = match lock(&self.path, false) {
+            Ok(f) => f,
+            Err(e) => {
+                if e.kind() == ErrorKind::WouldBlock {
+                    let pid = std::fs::read_to_string(&self.path).unwrap_or_default();
+                    let message = format!("already running as process {}", pid.trim());
+                    self.error = Error::new(ErrorKind::WouldBlock, message);
+                } else {
+                    self.error = e;
+                }
+                return -1;
+            }
+        };
+
+        let g = |f: &mut std::fs::File| -> std::io::Result<()> {
+            f.set_len(0)?;
+            f.seek(SeekFrom::Start(0))?;
+            f.write_all(format!("{}\n", std::process::id()).as_bytes())?;
+            f.sync_all()?;
+            return Ok(());
+        };
+
+        if let Err(e) = g(&mut f) {
+            self.error = e;
+            return -1;
+        }
+
+        self.pod = Some(f);
+        return 0;
+    }
+
+    /// Remove the PID file and release the lock, it's called on drop
+    pub fn release(&mut self) {
+        if self.pod.is_some() {
+            let _ = std::fs::remove_file(&self.path);
+            self.pod = None;
+        }
+    }
+
+    /// Read the PID of the file, returns -1 on error
+    pub fn read<T: AsRef<str>>(path: T) -> int {
+        let mut text = String::new();
+        let ret = std::fs::File::open(path.as_ref()).and_then(|mut f| f.read_to_string(&mut text));
+        if let Err(e) = ret {
+            return fail(e);
+        }
+
+        match text.trim().parse::<int>() {
+            Ok(pid) => {
+                return pid;
+            }
+            Err(e) => {
+                return fail(Error::new(ErrorKind::InvalidData, e));
+            }
+        }
+    }
+
+    /// Returns the path of the file
+    pub fn path(&self) -> &String {
+        return &self.path;
+    }
+
+    /// Returns the last error of calls
+    pub fn error(&self) -> &Error {
+        return &self.error;
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Mutex across processes, named by a lock file
+///
+/// ```rust,no_run
+/// use wsd::fs::*;
+///
+/// fn test() {
+///     let mut m = NamedMutex::new("/tmp/export.lock");
+///     if m.lock(5.0) != 0 {
+///         println!("Error: {}", m.error());
+///         return;
+///     }
+///
+///     // exclusive among processes
+///
+///     m.unlock();
+/// }
+/// ```
+pub struct NamedMutex {
+    pod: Option<std::fs::File>,
+    path: String,
+    error: Error,
+}
+
+impl NamedMutex {
+    /// Returns a mutex named by the lock file, the file is created on lock and kept
+    pub fn new<T: AsRef<str>>(path: T) -> Self {
+        return Self {
+            pod: None,
+            path: path.as_ref().to_string(),
+            error: Error::new(ErrorKind::Other, ""),
+        };
+    }
+
+    /// Acquire the mutex, wait at most the timeout as seconds, negative to wait forever.
+    /// Returns 0 on success, -1 on timeout or error.
+    pub fn lock(&mut self, timeout: f32) -> int {
+        if self.pod.is_some() {
+            return 0;
+        }
+
+        // NaN, infinite or too long to represent also waits forever
+        let deadline = match Duration::try_from_secs_f32(timeout).ok().and_then(|d| Instant::now().checked_add(d)) {
+            Some(deadline) => deadline,
+            None => return self.acquire(true),
+        };
+        let mut delay = Duration::from_millis(1);
+        loop {
+            if self.acquire(false) == 0 {
+                return 0;
+            }
+
+            if self.error.kind() != ErrorKind::WouldBlock {
+                return -1;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                self.error = Error::new(ErrorKind::TimedOut, "timed out waiting for the lock");
+                return -1;
+            }
+
+            std::thread::sleep(delay.min(deadline - now));
+            delay = (delay * 2).min(Duration::from_millis(50));
+        }
+    }
+
+    /// Acquire the mutex without waiting, returns 0 on success, -1 if it's locked or on error
+    pub fn try_lock(&mut self) -> int {
+        if self.pod.is_some() {
+            return 0;
+        }
+        return self.acquire(false);
+    }
+
+    fn acquire(&mut self, blocking: bool) -> int {
+        match lock(&self.path, blocking) {
+            Ok(f) => {
+                self.pod = Some(f);
+            }
+            Err(e) => {
+                self.error = e;
+                return -1;
+            }
+        }
+        return 0;
+    }
+
+    /// Release the mutex, it's called on drop
+    pub fn unlock(&mut self) {
+        self.pod = None;
+    }
+
+    /// Check if the mutex was held by this instance
+    pub fn is_locked(&self) -> bool {
+        return self.pod.is_some();
+    }
+
+    /// Returns the last error of calls
+    pub fn error(&self) -> &Error {
+        return &self.error;
+    }
+}
@@ -0,0 +1,312 @@
+//! In-memory file system, install it to run tests hermetically and inject faults
+use super::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Operations which could be failed by [`MemoryFs::inject`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    Open,
+    Read,
+    Write,
+    Flush,
+    Seek,
+    Mkdir,
+    Remove,
+    Rename,
+}
+
+#[derive(Default)]
+struct State {
+    files: HashMap<PathBuf, Arc<Mutex<Vec<u8>>>>,
+    dirs: HashSet<PathBuf>,
+    faults: HashMap<Fault, i32>,
+}
+
+/// A [`Backend`] keeping everything in memory
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use wsd::fs::*;
+///
+/// fn test() {
+///     let memory = Arc::new(MemoryFs::new());
+///     let _guard = scoped_backend(memory.clone());
+///
+///     // no space left on device
+///     memory.inject(Fault::Write, 28);
+///
+///     let mut f = File::new();
+///     f.open("test.txt", O_CREATE | O_RW);
+///     assert!(f.write("data") == 0);
+///     println!("Expected error: {}", f.error());
+/// }
+/// ```
+#[derive(Default, Clone)]
+pub struct MemoryFs {
+    state: Arc<Mutex<State>>,
+}
+
+impl MemoryFs {
+    /// Returns an empty file system
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Fail every following `op` with the OS error code `errno`, such as ENOSPC or EIO
+    pub fn inject(&self, op: Fault, errno: i32) {
+        self.state.lock().unwrap().faults.insert(op, errno);
+    }
+
+    /// Remove all injected faults
+    pub fn clear_faults(&self) {
+        self.state.lock().unwrap().faults.clear();
+    }
+
+    /// Check if a file or directory exists
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        let state = self.state.lock().unwrap();
+        let path = normalize(path.as_ref());
+        return state.files.contains_key(&path) || state.dirs.contains(&path);
+    }
+
+    /// Returns a copy of the file content
+    pub fn content<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let file = state.files.get(&normalize(path.as_ref()))?;
+        let data = file.lock().unwrap().clone();
+        return Some(data);
+    }
+
+    fn check(&self, op: Fault) -> std::io::Result<()> {
+        return check(&self.state, op);
+    }
+}
+
+fn check(state: &Mutex<State>, op: Fault) -> std::io::Result<()> {
+    if let Some(errno) = state.lock().unwrap().faults.get(&op) {
+        return Err(Error::from_raw_os_error(*errno));
+    }
+    return Ok(());
+}
+
+// strip the "." components, so "./a" and "a" are the same file
+fn normalize(path: &Path) -> PathBuf {
+    return path.components().filter(|c| *c != std::path::Component::CurDir).collect();
+}
+
+fn has_parent(state: &State, path: &Path) -> bool {
+    match path.parent() {
+        Some(parent) => {
+            return parent.as_os_str().is_empty() || parent.parent().is_none() || state.dirs.contains(parent);
+        }
+        None => {
+            return true;
+        }
+    }
+}
+
+impl Backend for MemoryFs {
+    fn open(&self, path: &Path, flags: u32) -> std::io::Result<Box<dyn Handle>> {
+        self.check(Fault::Open)?;
+
+        let path = normalize(path);
+        let mut state = self.state.lock().unwrap();
+        if state.dirs.contains(&path) {
+            return Err(Error::other("is a directory"));
+        }
+
+        let data = match state.files.get(&path) {
+            Some(data) => data.clone(),
+            None => {
+                if flags & O_CREATE == 0 {
+                    return Err(Error::new(ErrorKind::NotFound, "no such file or directory"));
+                }
+                if !has_parent(&state, &path) {
+                    return Err(Error::new(ErrorKind::NotFound, "no such file or directory"));
+                }
+                let data = Arc::new(Mutex::new(Vec::new()));
+                state.files.insert(path, data.clone());
+                data
+            }
+        };
+
+        if flags & O_TRUNCATE != 0 {
+            data.lock().unwrap().clear();
+        }
+
+        let handle = MemoryHandle {
+            state: self.state.clone(),
+            data,
+            pos: Arc::new(Mutex::new(0)),
+            flags,
+        };
+
+        return Ok(Box::new(handle));
+    }
+
+    fn mkdir(&self, path: &Path) -> std::io::Result<()> {
+        self.check(Fault::Mkdir)?;
+
+        let path = normalize(path);
+        let mut state = self.state.lock().unwrap();
+        for dir in path.ancestors() {
+            if dir.as_os_str().is_empty() {
+                continue;
+            }
+            if state.files.contains_key(dir) {
+                return Err(Error::new(ErrorKind::AlreadyExists, "file exists"));
+            }
+            state.dirs.insert(dir.to_path_buf());
+        }
+
+        return Ok(());
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.check(Fault::Remove)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.files.remove(&normalize(path)).is_none() {
+            return Err(Error::new(ErrorKind::NotFound, "no such file or directory"));
+        }
+
+        return Ok(());
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.check(Fault::Rename)?;
+
+        let to = normalize(to);
+        let mut state = self.state.lock().unwrap();
+        if !has_parent(&state, &to) {
+            return Err(Error::new(ErrorKind::NotFound, "no such file or directory"));
+        }
+
+        match state.files.remove(&normalize(from)) {
+            Some(data) => {
+                state.files.insert(to, data);
+            }
+            None => {
+                return Err(Error::new(ErrorKind::NotFound, "no such file or directory"));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+// An opened in-memory file, the content is shared with the file system,
+// the position is shared with the duplicates
+struct MemoryHandle {
+    state: Arc<Mutex<State>>,
+    data: Arc<Mutex<Vec<u8>>>,
+    pos: Arc<Mutex<u64>>,
+    flags: u32,
+}
+
+impl MemoryHandle {
+    fn read_from(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        check(&self.state, Fault::Read)?;
+        if self.flags & O_READ == 0 {
+            return Err(Error::new(ErrorKind::PermissionDenied, "file not opened for reading"));
+        }
+
+        let data = self.data.lock().unwrap();
+        let start = (offset as usize).min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+
+        return Ok(n);
+    }
+
+    // returns the end offset of the written data
+    fn write_to(&self, buf: &[u8], offset: u64) -> std::io::Result<u64> {
+        check(&self.state, Fault::Write)?;
+        if self.flags & (O_WRITE | O_APPEND) == 0 {
+            return Err(Error::new(ErrorKind::PermissionDenied, "file not opened for writing"));
+        }
+
+        // appending ignores the offset, as the disk does
+        let mut data = self.data.lock().unwrap();
+        let start = if self.flags & O_APPEND != 0 { data.len() } else { offset as usize };
+        let end = start + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+
+        return Ok(end as u64);
+    }
+}
+
+impl Read for MemoryHandle {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut pos = self.pos.lock().unwrap();
+        let n = self.read_from(buf, *pos)?;
+        *pos += n as u64;
+        return Ok(n);
+    }
+}
+
+impl Write for MemoryHandle {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut pos = self.pos.lock().unwrap();
+        *pos = self.write_to(buf, *pos)?;
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return check(&self.state, Fault::Flush);
+    }
+}
+
+impl Seek for MemoryHandle {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        check(&self.state, Fault::Seek)?;
+
+        let len = self.data.lock().unwrap().len() as i64;
+        let mut cur = self.pos.lock().unwrap();
+        let off = match pos {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::Current(n) => *cur as i64 + n,
+            SeekFrom::End(n) => len + n,
+        };
+
+        if off < 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid seek to a negative position"));
+        }
+
+        *cur = off as u64;
+        return Ok(*cur);
+    }
+}
+
+impl Handle for MemoryHandle {
+    fn try_clone(&self) -> std::io::Result<Box<dyn Handle>> {
+        let handle = MemoryHandle {
+            state: self.state.clone(),
+            data: self.data.clone(),
+            pos: self.pos.clone(),
+            flags: self.flags,
+        };
+        return Ok(Box::new(handle));
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        return self.read_from(buf, offset);
+    }
+
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+        self.write_to(buf, offset)?;
+        return Ok(buf.len());
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        check(&self.state, Fault::Write)?;
+        self.data.lock().unwrap().resize(len as usize, 0);
+        return Ok(());
+    }
+}
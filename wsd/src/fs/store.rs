@@ -0,0 +1,305 @@
+//! Embedded key-value store in an append-only log file
+use super::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+const SET: u8 = 1;
+const DELETE: u8 = 2;
+
+// FNV-1a, detects the torn records
+fn checksum(data: &[u8]) -> u32 {
+    let mut h: u32 = 0x811c9dc5;
+    for c in data {
+        h ^= *c as u32;
+        h = h.wrapping_mul(0x01000193);
+    }
+    return h;
+}
+
+// length, payload and checksum of the payload
+fn record(op: u8, key: &str, value: &[u8]) -> Vec<u8> {
+    let mut payload = vec![op];
+    payload.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    payload.extend_from_slice(key.as_bytes());
+    payload.extend_from_slice(value);
+
+    let mut buf = Vec::with_capacity(payload.len() + 8);
+    buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&payload);
+    buf.extend_from_slice(&checksum(&payload).to_le_bytes());
+    return buf;
+}
+
+// returns the op, key and value of a payload
+fn parse(payload: &[u8]) -> Option<(u8, String, Vec<u8>)> {
+    let op = *payload.first()?;
+    let n = u32::from_le_bytes(payload.get(1..5)?.try_into().ok()?) as usize;
+    let key = String::from_utf8(payload.get(5..5 + n)?.to_vec()).ok()?;
+    let value = payload[5 + n..].to_vec();
+    return Some((op, key, value));
+}
+
+/// Key-value store persisted in an append-only log, values are serialized as JSON
+///
+/// Every change is appended and synced to the file, so a crash loses at most the record
+/// being written, the truncated tail is discarded on the next open. Call [`Store::compact`]
+/// to drop the overwritten records.
+///
+/// ```rust,no_run
+/// use wsd::fs::*;
+///
+/// fn test() {
+///     let mut store = Store::new();
+///     if store.open("state.db") != 0 {
+///         println!("Error: {}", store.error());
+///         return;
+///     }
+///
+///     store.set("name", &"wsd");
+///     store.set("launches", &42);
+///
+///     let mut launches = 0;
+///     if store.get("launches", &mut launches) == 0 {
+///         store.set("launches", &(launches + 1));
+///     }
+///
+///     store.delete("name");
+///     store.compact();
+/// }
+/// ```
+pub struct Store {
+    file: File,
+    index: HashMap<String, Vec<u8>>,
+    records: usize,
+    error: Error,
+}
+
+impl Store {
+    /// Returns a new Store instance
+    pub fn new() -> Self {
+        return Self {
+            file: File::new(),
+            index: HashMap::new(),
+            records: 0,
+            error: Error::new(ErrorKind::Other, ""),
+        };
+    }
+
+    /// Open or create the store file and load the records, returns 0 on success, -1 on error
+    pub fn open<T: AsRef<str>>(&mut self, path: T) -> int {
+        self.index.clear();
+        self.records = 0;
+
+        if self.file.open(path, O_CREATE | O_RW | O_APPEND) != 0 {
+            return self.fail_file();
+        }
+
+        // replay the log, discard the torn tail
+        let end = self.file.seek(0, SEEK_END);
+        if end < 0 || self.file.rewind() != 0 {
+            return self.fail_file();
+        }
+
+        let mut off = 0;
+        while off < end {
+            let mut n = 0;
+            let mut sum = 0;
+            if self.file.read_u32_le(&mut n) != 0 || off + 8 + n as i64 > end {
+                break;
+            }
+
+            let mut payload = vec![0; n as usize];
+            if self.file.read_exact(&mut payload) != 0 || self.file.read_u32_le(&mut sum) != 0 {
+                return self.fail_file();
+            }
+
+            match parse(&payload) {
+                Some((op, key, value)) if sum == checksum(&payload) => {
+                    if op == SET {
+                        self.index.insert(key, value);
+                    } else {
+                        self.index.remove(&key);
+                    }
+                }
+                _ => {
+                    break;
+                }
+            }
+
+            self.records += 1;
+            off += 8 + n as i64;
+        }
+
+        if off < end && (self.file.truncate(off as u64) != 0 || self.file.sync() != 0) {
+            return self.fail_file();
+        }
+
+        return 0;
+    }
+
+    /// Get the value of the key, returns 0 on success, -1 if not found or on error
+    pub fn get<V: DeserializeOwned>(&mut self, key: &str, value: &mut V) -> int {
+        let data = match self.index.get(key) {
+            Some(data) => data,
+            None => {
+                self.error = Error::new(ErrorKind::NotFound, format!("key not found: {}", key));
+                return -1;
+            }
+        };
+
+        match serde_json::from_slice(data) {
+            Ok(v) => {
+                *value = v;
+            }
+            Err(e) => {
+                self.error = e.into();
+                return -1;
+            }
+        }
+
+        return 0;
+    }
+
+    /// Set the value of the key, returns 0 on success, -1 on error
+    pub fn set<V: Serialize + ?Sized>(&mut self, key: &str, value: &V) -> int {
+        let data = match serde_json::to_vec(value) {
+            Ok(data) => data,
+            Err(e) => {
+                self.error = e.into();
+                return -1;
+            }
+        };
+
+        if self.append(&record(SET, key, &data)) != 0 {
+            return -1;
+        }
+
+        self.index.insert(key.to_string(), data);
+        return 0;
+    }
+
+    /// Delete the key, returns 0 on success, -1 if not found or on error
+    pub fn delete(&mut self, key: &str) -> int {
+        if !self.index.contains_key(key) {
+            self.error = Error::new(ErrorKind::NotFound, format!("key not found: {}", key));
+            return -1;
+        }
+
+        if self.append(&record(DELETE, key, &[])) != 0 {
+            return -1;
+        }
+
+        self.index.remove(key);
+        return 0;
+    }
+
+    /// Check if the key exists
+    pub fn contains(&self, key: &str) -> bool {
+        return self.index.contains_key(key);
+    }
+
+    /// Returns the keys in arbitrary order
+    pub fn keys(&self) -> Vec<String> {
+        return self.index.keys().cloned().collect();
+    }
+
+    /// Returns the number of keys
+    pub fn len(&self) -> usize {
+        return self.index.len();
+    }
+
+    /// Check if the store has no keys
+    pub fn is_empty(&self) -> bool {
+        return self.index.is_empty();
+    }
+
+    /// Rewrite the file with the live keys only, returns 0 on success, -1 on error
+    pub fn compact(&mut self) -> int {
+        if self.file.is_none() {
+            self.error = Error::new(ErrorKind::NotConnected, "store not opened");
+            return -1;
+        }
+
+        let path = self.file.path().clone();
+        let temp = format!("{}.compact", path);
+
+        let mut buf = Vec::new();
+        let mut keys: Vec<&String> = self.index.keys().collect();
+        keys.sort();
+        for key in keys {
+            buf.extend_from_slice(&record(SET, key, &self.index[key]));
+        }
+
+        let mut f = File::new();
+        if f.open(&temp, O_CREATE | O_TRUNCATE | O_WRITE) != 0 || f.write_exact(&buf) != 0 || f.sync() != 0 {
+            self.error = std::mem::replace(&mut f.error, Error::new(ErrorKind::Other, ""));
+            remove(&temp);
+            return -1;
+        }
+        f.close();
+
+        // atomically replace the log
+        if rename(&temp, &path) != 0 {
+            self.error = last_error();
+            remove(&temp);
+            return -1;
+        }
+
+        if self.file.open(&path, O_RW | O_APPEND) != 0 {
+            return self.fail_file();
+        }
+
+        self.records = self.index.len();
+        return 0;
+    }
+
+    /// Returns the number of records in the file, including the overwritten ones
+    pub fn records(&self) -> usize {
+        return self.records;
+    }
+
+    /// Close the file
+    pub fn close(&mut self) {
+        self.file.close();
+        self.index.clear();
+        self.records = 0;
+    }
+
+    /// Returns the last error of calls
+    pub fn error(&self) -> &Error {
+        return &self.error;
+    }
+
+    // append a record and sync, a failed record is cut off so the later ones stay readable
+    fn append(&mut self, record: &[u8]) -> int {
+        if self.file.is_none() {
+            self.error = Error::new(ErrorKind::NotConnected, "store not opened");
+            return -1;
+        }
+
+        let end = self.file.seek(0, SEEK_END);
+        if end < 0 {
+            return self.fail_file();
+        }
+
+        if self.file.write_exact(record) != 0 || self.file.sync() != 0 {
+            let ret = self.fail_file();
+
+            // the next append syncs the truncation, or no more appends until reopened
+            if self.file.truncate(end as u64) != 0 {
+                self.file.close();
+            }
+            return ret;
+        }
+
+        self.records += 1;
+        return 0;
+    }
+
+    fn fail_file(&mut self) -> int {
+        self.error = std::mem::replace(&mut self.file.error, Error::new(ErrorKind::Other, ""));
+        return -1;
+    }
+}
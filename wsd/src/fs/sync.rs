@@ -0,0 +1,305 @@
+//! Mirror a directory tree into another, copying only the changed files
+use super::*;
+use std::path::PathBuf;
+
+/// Changes made by [`sync_dir`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAction {
+    /// The file or directory was created
+    Create,
+    /// The file was changed
+    Update,
+    /// The extra file or directory was deleted
+    Delete,
+}
+
+/// Options of [`sync_dir`]
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    checksum: bool,
+    delete: bool,
+    dry_run: bool,
+    exclude: Vec<String>,
+}
+
+impl SyncOptions {
+    /// Returns the default options, files are compared by size and mtime, no deletion
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Compare the content of files instead of the size and mtime
+    pub fn checksum(&mut self, yes: bool) -> &mut Self {
+        self.checksum = yes;
+        return self;
+    }
+
+    /// Delete the files in destination which don't exist in source
+    pub fn delete(&mut self, yes: bool) -> &mut Self {
+        self.delete = yes;
+        return self;
+    }
+
+    /// Report the changes without touching the destination
+    pub fn dry_run(&mut self, yes: bool) -> &mut Self {
+        self.dry_run = yes;
+        return self;
+    }
+
+    /// Skip the paths matching the pattern, neither copied nor deleted.
+    ///
+    /// The `*` matches any characters except `/`, the `?` matches one character. A pattern with
+    /// `/` is matched against the relative path, otherwise against the file name.
+    pub fn exclude<T: Into<String>>(&mut self, pattern: T) -> &mut Self {
+        self.exclude.push(pattern.into());
+        return self;
+    }
+
+    fn excluded(&self, rel: &Path) -> bool {
+        let path = rel.to_string_lossy().replace('\\', "/");
+        let name = rel.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        return self.exclude.iter().any(|p| {
+            let target = if p.contains('/') { path.as_str() } else { &name };
+            wildcard(p.trim_start_matches('/').as_bytes(), target.as_bytes())
+        });
+    }
+}
+
+fn wildcard(p: &[u8], s: &[u8]) -> bool {
+    match p.first() {
+        None => s.is_empty(),
+        Some(b'*') => (0..=s.len()).take_while(|i| *i == 0 || s[i - 1] != b'/').any(|i| wildcard(&p[1..], &s[i..])),
+        Some(b'?') => !s.is_empty() && s[0] != b'/' && wildcard(&p[1..], &s[1..]),
+        Some(c) => s.first() == Some(c) && wildcard(&p[1..], &s[1..]),
+    }
+}
+
+/// Mirror `src` into `dst`, returns the number of changes, -1 on error, see [`last_error`]
+///
+/// The directories are always on the disk, the [`Backend`] of the thread is ignored.
+///
+/// ```rust,no_run
+/// use wsd::fs::*;
+///
+/// fn test() {
+///     let mut options = SyncOptions::new();
+///     options.delete(true).exclude("*.tmp").exclude(".git");
+///     sync_dir_progress("assets", "/srv/www/assets", &options, |action, path| {
+///         println!("{:?} {}", action, path.display());
+///     });
+/// }
+/// ```
+pub fn sync_dir<S: AsRef<Path>, D: AsRef<Path>>(src: S, dst: D, options: &SyncOptions) -> int {
+    return sync_dir_progress(src, dst, options, |_, _| {});
+}
+
+/// Mirror like [`sync_dir`], the callback receives each change with the path relative to the directories
+pub fn sync_dir_progress<S, D, F>(src: S, dst: D, options: &SyncOptions, mut f: F) -> int
+where
+    S: AsRef<Path>,
+    D: AsRef<Path>,
+    F: FnMut(SyncAction, &Path),
+{
+    // the tree is walked on the disk, so the copies are made there too
+    let _guard = scoped_backend(std::sync::Arc::new(Disk));
+
+    let mut s = Sync {
+        src: src.as_ref().to_path_buf(),
+        dst: dst.as_ref().to_path_buf(),
+        options,
+        changes: 0,
+        f: &mut f,
+    };
+
+    let g = |s: &mut Sync| -> std::io::Result<()> {
+        if !std::fs::metadata(&s.src)?.is_dir() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "source is not a directory"));
+        }
+        if !s.dst.exists() {
+            s.change(SyncAction::Create, Path::new(""));
+            if !s.options.dry_run {
+                std::fs::create_dir_all(&s.dst)?;
+            }
+        }
+        return s.walk(Path::new(""));
+    };
+
+    if let Err(e) = g(&mut s) {
+        return fail(e);
+    }
+
+    return s.changes;
+}
+
+struct Sync<'a> {
+    src: PathBuf,
+    dst: PathBuf,
+    options: &'a SyncOptions,
+    changes: int,
+    f: &'a mut dyn FnMut(SyncAction, &Path),
+}
+
+impl<'a> Sync<'a> {
+    fn change(&mut self, action: SyncAction, rel: &Path) {
+        self.changes += 1;
+        (self.f)(action, rel);
+    }
+
+    // sync the directory of the relative path
+    fn walk(&mut self, rel: &Path) -> std::io::Result<()> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(self.src.join(rel))? {
+            names.push(entry?.file_name());
+        }
+        names.sort();
+
+        for name in &names {
+            let rel = rel.join(name);
+            if self.options.excluded(&rel) {
+                continue;
+            }
+            self.entry(&rel)?;
+        }
+
+        if !self.options.delete {
+            return Ok(());
+        }
+
+        // the directory was not created in dry run
+        let dir = self.dst.join(rel);
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        let mut extras = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            if !names.contains(&name) {
+                extras.push(name);
+            }
+        }
+        extras.sort();
+
+        for name in extras {
+            let rel = rel.join(name);
+            if self.options.excluded(&rel) {
+                continue;
+            }
+            self.change(SyncAction::Delete, &rel);
+            if !self.options.dry_run {
+                remove_all(&self.dst.join(&rel))?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn entry(&mut self, rel: &Path) -> std::io::Result<()> {
+        let src = self.src.join(rel);
+        let dst = self.dst.join(rel);
+        let meta = std::fs::symlink_metadata(&src)?;
+        let target = std::fs::symlink_metadata(&dst).ok();
+
+        // replace an entry of another type
+        let mut action = SyncAction::Create;
+        if let Some(t) = &target {
+            if t.file_type() != meta.file_type() {
+                if !self.options.dry_run {
+                    remove_all(&dst)?;
+                }
+            } else if meta.is_dir() {
+                return self.walk(rel);
+            } else if !self.changed(&src, &dst, &meta, t)? {
+                return Ok(());
+            } else {
+                action = SyncAction::Update;
+            }
+        }
+
+        self.change(action, rel);
+        if self.options.dry_run {
+            if meta.is_dir() {
+                return self.walk(rel);
+            }
+            return Ok(());
+        }
+
+        if meta.is_dir() {
+            std::fs::create_dir(&dst)?;
+            return self.walk(rel);
+        }
+
+        // replaced rather than rewritten, the destination may be read-only
+        if action == SyncAction::Update {
+            std::fs::remove_file(&dst)?;
+        }
+
+        if meta.file_type().is_symlink() {
+            let link = std::fs::read_link(&src)?;
+            return symlink(&link, &dst);
+        }
+
+        if copy(&src, &dst) < 0 {
+            return Err(last_error());
+        }
+
+        // keep the mtime for the next comparison, the copy has the permissions of the source,
+        // so it's set through a read-only handle
+        let f = std::fs::File::open(&dst)?;
+        f.set_modified(meta.modified()?)?;
+
+        return Ok(());
+    }
+
+    fn changed(&self, src: &Path, dst: &Path, a: &std::fs::Metadata, b: &std::fs::Metadata) -> std::io::Result<bool> {
+        if a.file_type().is_symlink() {
+            return Ok(std::fs::read_link(src)? != std::fs::read_link(dst)?);
+        }
+
+        if a.len() != b.len() {
+            return Ok(true);
+        }
+
+        if !self.options.checksum {
+            return Ok(a.modified()? != b.modified()?);
+        }
+
+        return Ok(!same_content(src, dst)?);
+    }
+}
+
+fn same_content(a: &Path, b: &Path) -> std::io::Result<bool> {
+    use std::io::Read;
+
+    let mut x = std::fs::File::open(a)?;
+    let mut y = std::fs::File::open(b)?;
+    let mut p = vec![0; 64 << 10];
+    let mut q = vec![0; 64 << 10];
+
+    loop {
+        let n = x.read(&mut p)?;
+        if n == 0 {
+            return Ok(y.read(&mut q[..1])? == 0);
+        }
+        if y.read_exact(&mut q[..n]).is_err() || p[..n] != q[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+fn remove_all(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        return std::fs::remove_dir_all(path);
+    }
+    return std::fs::remove_file(path);
+}
+
+#[cfg(unix)]
+fn symlink(link: &Path, dst: &Path) -> std::io::Result<()> {
+    return std::os::unix::fs::symlink(link, dst);
+}
+
+#[cfg(not(unix))]
+fn symlink(_link: &Path, _dst: &Path) -> std::io::Result<()> {
+    return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "symlinks are not supported"));
+}
@@ -0,0 +1,181 @@
+//! Create and extract tar archives, optionally compressed with gzip
+//!
+//! ```rust,no_run
+//! use wsd::fs::*;
+//!
+//! fn test() {
+//!     let mut options = tar::Options::new();
+//!     options.gzip(true);
+//!
+//!     if tar::create("bundle.tar.gz", &["assets", "config.json"], &options) != 0 {
+//!         println!("Error: {}", last_error());
+//!     }
+//!
+//!     if tar::extract("bundle.tar.gz", "/tmp/bundle") != 0 {
+//!         println!("Error: {}", last_error());
+//!     }
+//! }
+//! ```
+use super::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::path::{Component, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Options of [`create`]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    gzip: bool,
+    follow: bool,
+}
+
+impl Options {
+    /// Returns the default options, no compression and symlinks archived as links
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Whether to compress the archive with gzip
+    pub fn gzip(&mut self, zip: bool) -> &mut Self {
+        self.gzip = zip;
+        return self;
+    }
+
+    /// Whether to archive the targets of symlinks instead of the links
+    pub fn follow_symlinks(&mut self, follow: bool) -> &mut Self {
+        self.follow = follow;
+        return self;
+    }
+}
+
+/// Create an archive of files and directories, modes and mtimes are preserved.
+///
+/// Entries are named by the given paths, with the leading `/`, `.` and `..` components stripped.
+pub fn create<O, P>(out: O, paths: &[P], options: &Options) -> int
+where
+    O: AsRef<Path>,
+    P: AsRef<Path>,
+{
+    let g = || -> std::io::Result<()> {
+        let f = std::fs::File::create(out.as_ref())?;
+        if options.gzip {
+            let z = GzEncoder::new(f, Compression::default());
+            let z = append(z, paths, options)?;
+            z.finish()?.sync_all()?;
+        } else {
+            let f = append(f, paths, options)?;
+            f.sync_all()?;
+        }
+        return Ok(());
+    };
+
+    if let Err(e) = g() {
+        let _ = std::fs::remove_file(out.as_ref());
+        return fail(e);
+    }
+
+    return 0;
+}
+
+fn append<W: Write, P: AsRef<Path>>(w: W, paths: &[P], options: &Options) -> std::io::Result<W> {
+    let mut builder = ::tar::Builder::new(w);
+    builder.follow_symlinks(options.follow);
+
+    for path in paths {
+        let path = path.as_ref();
+        let name = entry_name(path);
+        if name.as_os_str().is_empty() {
+            builder.append_dir_all(".", path)?;
+        } else if std::fs::symlink_metadata(path)?.is_dir() {
+            builder.append_dir_all(&name, path)?;
+        } else {
+            builder.append_path_with_name(path, &name)?;
+        }
+    }
+
+    return builder.into_inner();
+}
+
+// archive name of a path, the root, "." and ".." components are stripped
+fn entry_name(path: &Path) -> PathBuf {
+    return path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+}
+
+/// Extract an archive into a directory, gzip is detected automatically.
+///
+/// Modes and mtimes are restored, the archive is rejected if any entry would be written outside of the directory.
+pub fn extract<A, D>(archive: A, dir: D) -> int
+where
+    A: AsRef<Path>,
+    D: AsRef<Path>,
+{
+    let g = || -> std::io::Result<()> {
+        let mut reader = BufReader::new(std::fs::File::open(archive.as_ref())?);
+        let magic = {
+            let buf = std::io::BufRead::fill_buf(&mut reader)?;
+            buf.len() >= 2 && buf[0] == 0x1f && buf[1] == 0x8b
+        };
+
+        std::fs::create_dir_all(dir.as_ref())?;
+        if magic {
+            unpack(GzDecoder::new(reader), dir.as_ref())?;
+        } else {
+            unpack(reader, dir.as_ref())?;
+        }
+        return Ok(());
+    };
+
+    if let Err(e) = g() {
+        return fail(e);
+    }
+
+    return 0;
+}
+
+fn traversal(path: &Path) -> Error {
+    return Error::new(ErrorKind::InvalidData, format!("path traversal entry: {}", path.display()));
+}
+
+fn unpack<R: Read>(r: R, dir: &Path) -> std::io::Result<()> {
+    let mut archive = ::tar::Archive::new(r);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+
+    // the directories are extracted last, so writing their files won't reset the mtimes,
+    // and a read-only directory won't block its own files
+    let mut dirs = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // no absolute paths or "..", the unpack_in() also refuses symlinks escaping the directory
+        let unsafe_path = path
+            .components()
+            .any(|c| matches!(c, Component::ParentDir | Component::RootDir | Component::Prefix(_)));
+        if unsafe_path {
+            return Err(traversal(&path));
+        }
+
+        if entry.header().entry_type() == ::tar::EntryType::Directory {
+            dirs.push((path, entry));
+        } else if !entry.unpack_in(dir)? {
+            return Err(traversal(&path));
+        }
+    }
+
+    // the children before their parents
+    dirs.sort_by(|a, b| b.0.cmp(&a.0));
+    for (path, mut entry) in dirs {
+        if !entry.unpack_in(dir)? {
+            return Err(traversal(&path));
+        }
+
+        // the tar crate restores the mtimes of files only
+        let mtime = UNIX_EPOCH + Duration::from_secs(entry.header().mtime()?);
+        std::fs::File::open(dir.join(&path))?.set_modified(mtime)?;
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,220 @@
+//! Extended attributes of files
+//!
+//! On a file system without extended attributes, the calls fail with the error kind
+//! [`std::io::ErrorKind::Unsupported`].
+//!
+//! ```rust,no_run
+//! use wsd::fs::*;
+//!
+//! fn test() {
+//!     if setxattr("cache.bin", "user.source", "https://docs.rs") != 0 {
+//!         println!("Error: {}", last_error());
+//!     }
+//!
+//!     let mut value = Vec::new();
+//!     if getxattr("cache.bin", "user.source", &mut value) >= 0 {
+//!         println!("source: {}", String::from_utf8_lossy(&value));
+//!     }
+//! }
+//! ```
+use super::*;
+use std::ffi::CString;
+use std::io::{Error, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+
+// the target of the calls
+enum Target {
+    Path(CString),
+    Fd(i32),
+}
+
+fn cstring(s: &[u8]) -> std::io::Result<CString> {
+    return CString::new(s).map_err(|e| Error::new(ErrorKind::InvalidInput, e));
+}
+
+fn path_target(path: &Path) -> std::io::Result<Target> {
+    return Ok(Target::Path(cstring(path.as_os_str().as_bytes())?));
+}
+
+fn check(ret: isize) -> std::io::Result<usize> {
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    return Ok(ret as usize);
+}
+
+// query the size then fetch, retry if the value grew meanwhile
+fn fetch<F: Fn(*mut libc::c_void, usize) -> isize>(f: F) -> std::io::Result<Vec<u8>> {
+    loop {
+        let size = check(f(std::ptr::null_mut(), 0))?;
+        let mut buf = vec![0u8; size];
+        match check(f(buf.as_mut_ptr() as *mut libc::c_void, buf.len())) {
+            Ok(n) => {
+                buf.truncate(n);
+                return Ok(buf);
+            }
+            Err(e) if e.raw_os_error() == Some(libc::ERANGE) => {}
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+}
+
+fn get(target: &Target, name: &str) -> std::io::Result<Vec<u8>> {
+    let name = cstring(name.as_bytes())?;
+    return fetch(|buf, size| unsafe {
+        match target {
+            Target::Path(p) => libc::getxattr(p.as_ptr(), name.as_ptr(), buf, size),
+            Target::Fd(fd) => libc::fgetxattr(*fd, name.as_ptr(), buf, size),
+        }
+    });
+}
+
+fn set(target: &Target, name: &str, value: &[u8]) -> std::io::Result<()> {
+    let name = cstring(name.as_bytes())?;
+    let data = value.as_ptr() as *const libc::c_void;
+    let ret = unsafe {
+        match target {
+            Target::Path(p) => libc::setxattr(p.as_ptr(), name.as_ptr(), data, value.len(), 0),
+            Target::Fd(fd) => libc::fsetxattr(*fd, name.as_ptr(), data, value.len(), 0),
+        }
+    };
+    check(ret as isize)?;
+    return Ok(());
+}
+
+fn list(target: &Target) -> std::io::Result<Vec<String>> {
+    let buf = fetch(|buf, size| unsafe {
+        match target {
+            Target::Path(p) => libc::listxattr(p.as_ptr(), buf as *mut libc::c_char, size),
+            Target::Fd(fd) => libc::flistxattr(*fd, buf as *mut libc::c_char, size),
+        }
+    })?;
+
+    // names are null terminated
+    let names = buf
+        .split(|c| *c == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect();
+    return Ok(names);
+}
+
+fn delete(target: &Target, name: &str) -> std::io::Result<()> {
+    let name = cstring(name.as_bytes())?;
+    let ret = unsafe {
+        match target {
+            Target::Path(p) => libc::removexattr(p.as_ptr(), name.as_ptr()),
+            Target::Fd(fd) => libc::fremovexattr(*fd, name.as_ptr()),
+        }
+    };
+    check(ret as isize)?;
+    return Ok(());
+}
+
+/// Get an extended attribute, returns the length of the value, -1 on error, see [`last_error`]
+pub fn getxattr<P: AsRef<Path>>(path: P, name: &str, value: &mut Vec<u8>) -> int {
+    match path_target(path.as_ref()).and_then(|t| get(&t, name)) {
+        Ok(v) => {
+            *value = v;
+            return value.len() as int;
+        }
+        Err(e) => {
+            return fail(e);
+        }
+    }
+}
+
+/// Set an extended attribute, returns 0 on success, -1 on error, see [`last_error`]
+pub fn setxattr<P: AsRef<Path>, V: AsRef<[u8]>>(path: P, name: &str, value: V) -> int {
+    if let Err(e) = path_target(path.as_ref()).and_then(|t| set(&t, name, value.as_ref())) {
+        return fail(e);
+    }
+    return 0;
+}
+
+/// List the names of extended attributes, returns the number of names, -1 on error, see [`last_error`]
+pub fn listxattr<P: AsRef<Path>>(path: P, names: &mut Vec<String>) -> int {
+    match path_target(path.as_ref()).and_then(|t| list(&t)) {
+        Ok(v) => {
+            *names = v;
+            return names.len() as int;
+        }
+        Err(e) => {
+            return fail(e);
+        }
+    }
+}
+
+/// Remove an extended attribute, returns 0 on success, -1 on error, see [`last_error`]
+pub fn removexattr<P: AsRef<Path>>(path: P, name: &str) -> int {
+    if let Err(e) = path_target(path.as_ref()).and_then(|t| delete(&t, name)) {
+        return fail(e);
+    }
+    return 0;
+}
+
+impl File {
+    // the opened descriptor as target
+    fn xattr_target(&mut self) -> std::io::Result<Target> {
+        if self.is_none() {
+            return Err(Error::new(ErrorKind::NotConnected, "file not opened"));
+        }
+        match self.io().file() {
+            Some(f) => {
+                return Ok(Target::Fd(f.as_raw_fd()));
+            }
+            None => {
+                return Err(Error::new(ErrorKind::Unsupported, "not a disk file"));
+            }
+        }
+    }
+
+    /// Get an extended attribute of the opened file, returns the length of the value, -1 on error
+    pub fn getxattr(&mut self, name: &str, value: &mut Vec<u8>) -> int {
+        match self.xattr_target().and_then(|t| get(&t, name)) {
+            Ok(v) => {
+                *value = v;
+                return value.len() as int;
+            }
+            Err(e) => {
+                self.error = e;
+                return -1;
+            }
+        }
+    }
+
+    /// Set an extended attribute of the opened file, returns 0 on success, -1 on error
+    pub fn setxattr<V: AsRef<[u8]>>(&mut self, name: &str, value: V) -> int {
+        if let Err(e) = self.xattr_target().and_then(|t| set(&t, name, value.as_ref())) {
+            self.error = e;
+            return -1;
+        }
+        return 0;
+    }
+
+    /// List the names of extended attributes of the opened file, returns the number of names, -1 on error
+    pub fn listxattr(&mut self, names: &mut Vec<String>) -> int {
+        match self.xattr_target().and_then(|t| list(&t)) {
+            Ok(v) => {
+                *names = v;
+                return names.len() as int;
+            }
+            Err(e) => {
+                self.error = e;
+                return -1;
+            }
+        }
+    }
+
+    /// Remove an extended attribute of the opened file, returns 0 on success, -1 on error
+    pub fn removexattr(&mut self, name: &str) -> int {
+        if let Err(e) = self.xattr_target().and_then(|t| delete(&t, name)) {
+            self.error = e;
+            return -1;
+        }
+        return 0;
+    }
+}
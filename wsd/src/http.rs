@@ -1,13 +1,24 @@
 //! Provides most simple ways to make http request, simple as what JavaScript dose!
 use reqwest::blocking::{Body, Client, RequestBuilder, Response};
-use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::fmt;
-use std::str::FromStr;
-use std::time::Duration;
+use std::io::Read;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
+pub use reqwest::header::{HeaderMap, HeaderValue};
 pub use reqwest::Method;
 
+mod download;
+mod form;
+mod retry;
+pub mod url;
+
+pub use download::*;
+pub use form::*;
+pub use retry::*;
+
 #[allow(rustdoc::bare_urls)]
 
 /// Most simple way to make http request, using keep-alive connection pooling.
@@ -17,7 +28,7 @@ pub use reqwest::Method;
 /// fn test() {
 ///    let mut c = Request::new(Method::POST, "https://docs.rs");
 ///    c.gzip(true);
-///    c.timeout(5);
+///    c.timeout(5.0);
 ///    c.header("TOKEN", "1234567890");
 ///    c.send("{id: 100}", |data| {
 ///        println!("Data: {}", data.text());
@@ -29,24 +40,154 @@ pub struct Request {
     url: String,
     method: Method,
     inner: Option<Client>,
-    headers: HashMap<String, String>,
+    headers: Vec<(String, String)>,
+    query: Vec<(String, String)>,
     error: String,
-    timeout: f32,
+    connect_timeout: f32,
+    read_timeout: Option<f32>,
+    total_timeout: f32,
     gzip: bool,
+    retry: Option<Retry>,
 }
 
 pub struct Data {
     status: u16,
-    data: String,
+    data: Vec<u8>,
+    text: OnceLock<String>,
     headers: HashMap<String, String>,
+    raw: HeaderMap,
+    attempts: u32,
+    error: String,
+    kind: ErrorKind,
+}
+
+// the timeouts of get and post
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The status of [`Data`] when no response was received, see [`Data::error`]
+pub const TRANSPORT_ERROR: u16 = 0;
+
+/// The kind of a transport error, see [`Data::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// No error
+    None,
+    /// The url or the request was invalid
+    Builder,
+    /// The host name could not be resolved
+    Dns,
+    /// The connection was refused or failed
+    Connect,
+    /// The TLS handshake or certificate verification failed
+    Tls,
+    /// The request timed out
+    Timeout,
+    /// Too many redirects or a redirect loop
+    Redirect,
+    /// The response body could not be read or decoded
+    Body,
+    /// Any other error
+    Other,
 }
 
+impl ErrorKind {
+    // classify the error, DNS and TLS failures are told by the error chain
+    fn from(e: &reqwest::Error) -> ErrorKind {
+        if e.is_timeout() {
+            return ErrorKind::Timeout;
+        }
+        if e.is_builder() {
+            return ErrorKind::Builder;
+        }
+        if e.is_redirect() {
+            return ErrorKind::Redirect;
+        }
+        if e.is_body() || e.is_decode() {
+            return ErrorKind::Body;
+        }
+
+        let mut chain = String::new();
+        let mut source = std::error::Error::source(e);
+        while let Some(x) = source {
+            chain.push_str(&x.to_string().to_ascii_lowercase());
+            chain.push('\n');
+            source = x.source();
+        }
+
+        if chain.contains("dns error") || chain.contains("failed to lookup address") {
+            return ErrorKind::Dns;
+        }
+        if chain.contains("certificate") || chain.contains("tls") || chain.contains("ssl") {
+            return ErrorKind::Tls;
+        }
+        if e.is_connect() {
+            return ErrorKind::Connect;
+        }
+        return ErrorKind::Other;
+    }
+
+    // classify the error of reading the body
+    fn from_io(e: &std::io::Error) -> ErrorKind {
+        if e.kind() == std::io::ErrorKind::TimedOut {
+            return ErrorKind::Timeout;
+        }
+        if let Some(x) = e.get_ref().and_then(|x| x.downcast_ref::<reqwest::Error>()) {
+            return ErrorKind::from(x);
+        }
+        return ErrorKind::Body;
+    }
+}
+
+fn seconds(s: f32) -> Duration {
+    return Duration::from_millis((s * 1000.0) as u64);
+}
+
+// read the whole body by the read timeout of the client, the deadline is checked between the reads
+fn read_body(response: &mut Response, deadline: Option<Instant>) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buf = vec![0; 64 << 10];
+    loop {
+        let n = match response.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        data.extend_from_slice(&buf[..n]);
+
+        if deadline.is_some_and(|d| Instant::now() > d) {
+            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "operation timed out"));
+        }
+    }
+    return Ok(data);
+}
+
+// the charset parameter of a content type
+fn charset(content_type: &str) -> Option<String> {
+    for param in content_type.split(';').skip(1) {
+        if let Some((key, value)) = param.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("charset") {
+                return Some(value.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    return None;
+}
+
+// the repeated headers are joined by ", ", the non-UTF-8 values are decoded lossily
 fn get_headers(input: &HeaderMap) -> HashMap<String, String> {
     let mut headers: HashMap<String, String> = HashMap::new();
     for (key, value) in input {
         let k = String::from(key.as_str());
-        let v = String::from(value.to_str().unwrap_or(""));
-        headers.insert(k, v);
+        let v = String::from_utf8_lossy(value.as_bytes());
+        headers
+            .entry(k)
+            .and_modify(|x| {
+                x.push_str(", ");
+                x.push_str(&v);
+            })
+            .or_insert_with(|| v.into_owned());
     }
 
     return headers;
@@ -58,6 +199,10 @@ impl fmt::Debug for Request {
             .field("method", &self.method)
             .field("url", &self.url)
             .field("headers", &self.headers)
+            .field("query", &self.query)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read())
+            .field("total_timeout", &self.total_timeout)
             .finish()
     }
 }
@@ -68,119 +213,365 @@ impl Request {
             url: url.as_ref().to_string(),
             method: method,
             inner: None,
-            headers: HashMap::new(),
+            headers: Vec::new(),
+            query: Vec::new(),
             error: "".into(),
-            timeout: 10.0,
+            connect_timeout: 10.0,
+            read_timeout: None,
+            total_timeout: 0.0,
             gzip: false,
+            retry: None,
         };
     }
 
     /// Whether to enable gzip
     pub fn gzip(&mut self, zip: bool) -> &mut Self {
         self.gzip = zip;
+        self.inner = None;
         return self;
     }
 
-    /// Set timeout as seconds
+    /// Set the total timeout as seconds, 0 for no timeout, see [`Request::total_timeout`]
     pub fn timeout(&mut self, seconds: f32) -> &mut Self {
-        self.timeout = seconds;
+        return self.total_timeout(seconds);
+    }
+
+    /// Set the timeout of connecting as seconds, 0 for no timeout, 10 by default
+    pub fn connect_timeout(&mut self, seconds: f32) -> &mut Self {
+        self.connect_timeout = seconds;
+        self.inner = None;
+        return self;
+    }
+
+    /// Set the max seconds to wait for the response head and for each read of the body,
+    /// 0 for no timeout, 10 by default or none if only a total timeout was set
+    ///
+    /// The wait for the head includes sending the request body, so a large or slow upload,
+    /// such as a [`Form`] with file parts, must raise or disable it.
+    pub fn read_timeout(&mut self, seconds: f32) -> &mut Self {
+        self.read_timeout = Some(seconds);
+        self.inner = None;
+        return self;
+    }
+
+    /// Set the timeout of the whole request including the body as seconds, 0 for no timeout by default
+    ///
+    /// If a shorter read timeout was set, the total timeout is checked between the reads of the body.
+    pub fn total_timeout(&mut self, seconds: f32) -> &mut Self {
+        self.total_timeout = seconds;
+        self.inner = None;
         return self;
     }
 
-    /// Insert header before send
+    // the read timeout, the default one never caps a total timeout
+    fn read(&self) -> f32 {
+        return match self.read_timeout {
+            Some(seconds) => seconds,
+            None if self.total_timeout > 0.0 => 0.0,
+            None => 10.0,
+        };
+    }
+
+    // the total timeout checked by ourselves, when the client has to keep a shorter read timeout
+    fn deadline(&self) -> Option<Instant> {
+        let read = self.read();
+        if read > 0.0 && read < self.total_timeout {
+            return Some(Instant::now() + seconds(self.total_timeout));
+        }
+        return None;
+    }
+
+    /// Insert header before send, a header set more than once is sent with all the values
     pub fn header<K: Into<String>>(&mut self, key: K, value: K) -> &mut Self {
-        self.headers.insert(key.into(), value.into());
+        self.headers.push((key.into(), value.into()));
+        return self;
+    }
+
+    /// Append a query parameter to the url, both are percent-encoded
+    pub fn query<K: AsRef<str>, V: AsRef<str>>(&mut self, key: K, value: V) -> &mut Self {
+        self.query.push((key.as_ref().to_string(), value.as_ref().to_string()));
         return self;
     }
 
     fn build(&self) -> RequestBuilder {
         let c = self.inner.as_ref().unwrap();
-        let mut x = c.request(self.method.clone(), self.url.clone());
+        let mut x = c.request(self.method.clone(), url::with_query(&self.url, &self.query));
         for (key, value) in &self.headers {
             x = x.header(key, value);
         }
+        if self.total_timeout > 0.0 && self.deadline().is_none() {
+            x = x.timeout(seconds(self.total_timeout));
+        }
         return x;
     }
 
-    /// Send the request
-    pub fn send<DATA: Into<Body>, F: FnMut(Data)>(&mut self, data: DATA, mut f: F) -> i32 {
-        let duration = Duration::from_millis((self.timeout * 1000.0) as u64);
-        // build client once
-        if self.inner.is_none() {
-            let c = Client::builder()
-                .gzip(self.gzip)
-                .timeout(duration)
-                .build();
-            match c {
-                Ok(client) => {
-                    self.inner = Some(client);
-                }
-                Err(e) => {
-                    self.error = e.to_string();
-                    return -1;
-                }
+    /// Returns the last error of calls
+    pub fn error(&self) -> &String {
+        return &self.error;
+    }
+
+    // build client once, rebuilt if the config was changed
+    fn client(&mut self) -> i32 {
+        if self.inner.is_some() {
+            return 0;
+        }
+
+        let mut c = Client::builder().gzip(self.gzip);
+        if self.connect_timeout > 0.0 {
+            c = c.connect_timeout(seconds(self.connect_timeout));
+        }
+
+        // the blocking client waits for the head and each read by the timeout
+        let read = self.read();
+        if read > 0.0 {
+            c = c.timeout(seconds(read));
+        } else {
+            c = c.timeout(None);
+        }
+
+        match c.build() {
+            Ok(client) => {
+                self.inner = Some(client);
+            }
+            Err(e) => {
+                self.error = e.to_string();
+                return -1;
             }
         }
 
+        return 0;
+    }
+
+    /// Send the request
+    pub fn send<DATA: Into<Body>, F: FnMut(Data)>(&mut self, data: DATA, f: F) -> i32 {
+        if self.client() != 0 {
+            return -1;
+        }
+
         // build request
         let mut x = self.build();
         x = x.body(data);
 
-        // send
-        let ret = x.send();
+        return self.exec(x, f);
+    }
+
+    /// Send the value as a JSON body, such as a struct declared by [`crate::json::json`]
+    pub fn send_json<'a, T: crate::json::JSON<'a>, F: FnMut(Data)>(&mut self, value: &T, f: F) -> i32 {
+        let body = match serde_json::to_vec(value) {
+            Ok(body) => body,
+            Err(e) => {
+                self.error = e.to_string();
+                return -1;
+            }
+        };
+
+        if self.client() != 0 {
+            return -1;
+        }
+
+        let mut x = self.build();
+        x = x.header("Content-Type", "application/json").body(body);
+
+        return self.exec(x, f);
+    }
+
+    /// Send the pairs as an `application/x-www-form-urlencoded` body
+    pub fn form<I, K, V, F>(&mut self, pairs: I, f: F) -> i32
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+        F: FnMut(Data),
+    {
+        if self.client() != 0 {
+            return -1;
+        }
+
+        let mut x = self.build();
+        x = x.header("Content-Type", "application/x-www-form-urlencoded");
+        x = x.body(url::encode_query(pairs));
+
+        return self.exec(x, f);
+    }
+
+    // send the built request and read the whole response
+    fn exec<F: FnMut(Data)>(&mut self, x: RequestBuilder, mut f: F) -> i32 {
+        let deadline = self.deadline();
+        let (ret, attempts) = self.execute(x);
         if let Err(e) = ret {
             self.error = e.to_string();
             return -1;
         }
 
         let response = ret.unwrap();
-        let status = response.status().as_u16();
-        let headers = get_headers(response.headers());
-
-        // moved occured inside of the text()
-        let t = response.text();
-        if let Err(e) = t {
+        let upcall = Data::from(response, deadline);
+        if let Err(e) = upcall {
             self.error = e.to_string();
             return -1;
         }
 
-        let upcall = Data {
-            status: status,
-            data: t.unwrap(),
-            headers: headers,
-        };
-
-        f(upcall);
+        let mut data = upcall.unwrap();
+        data.attempts = attempts;
+        f(data);
 
         return 0;
     }
 }
 
 impl Data {
-    /// Get HTTP status code
+    fn new(status: u16) -> Self {
+        return Data {
+            status,
+            data: Vec::new(),
+            text: OnceLock::new(),
+            headers: HashMap::new(),
+            raw: HeaderMap::new(),
+            attempts: 1,
+            error: "".into(),
+            kind: ErrorKind::None,
+        };
+    }
+
+    // a transport error instead of a response
+    fn fail(e: reqwest::Error) -> Self {
+        let mut data = Data::new(TRANSPORT_ERROR);
+        data.error = e.to_string();
+        data.kind = ErrorKind::from(&e);
+        return data;
+    }
+
+    // read the whole body, the status and headers are kept if the body failed
+    fn complete(ret: Result<Response, reqwest::Error>) -> Self {
+        let mut response = match ret {
+            Ok(r) => r,
+            Err(e) => return Data::fail(e),
+        };
+
+        let mut data = Data::new(response.status().as_u16());
+        data.headers = get_headers(response.headers());
+        data.raw = response.headers().clone();
+        match read_body(&mut response, None) {
+            Ok(b) => {
+                data.data = b;
+            }
+            Err(e) => {
+                data.error = e.to_string();
+                data.kind = ErrorKind::from_io(&e);
+            }
+        }
+
+        return data;
+    }
+
+    // read the whole body
+    fn from(mut response: Response, deadline: Option<Instant>) -> std::io::Result<Data> {
+        let mut data = Data::new(response.status().as_u16());
+        data.headers = get_headers(response.headers());
+        data.raw = response.headers().clone();
+        data.data = read_body(&mut response, deadline)?;
+        return Ok(data);
+    }
+
+    /// Get HTTP status code, [`TRANSPORT_ERROR`] if no response was received
     pub fn status(&self) -> u16 {
         return self.status;
     }
 
-    /// Get reponse as text
-    pub fn text(&self) -> &String {
+    /// Returns the transport error, empty if none
+    pub fn error(&self) -> &String {
+        return &self.error;
+    }
+
+    /// Returns the kind of the transport error, [`ErrorKind::None`] if none
+    pub fn kind(&self) -> ErrorKind {
+        return self.kind;
+    }
+
+    /// Check if the request timed out
+    pub fn is_timeout(&self) -> bool {
+        return self.kind == ErrorKind::Timeout;
+    }
+
+    /// Check if the connection failed, including DNS and TLS failures
+    pub fn is_connect(&self) -> bool {
+        return matches!(self.kind, ErrorKind::Dns | ErrorKind::Connect | ErrorKind::Tls);
+    }
+
+    /// Get the number of attempts made, more than 1 if the request was retried, see [`Retry`]
+    pub fn attempts(&self) -> u32 {
+        return self.attempts;
+    }
+
+    /// Get reponse as raw bytes
+    pub fn bytes(&self) -> &[u8] {
         return &self.data;
     }
 
+    /// Get reponse as text, decoded by the charset of `Content-Type`, UTF-8 by default
+    pub fn text(&self) -> &String {
+        return self.text.get_or_init(|| {
+            let charset = self.header("content-type").and_then(|t| charset(&t));
+            let encoding = charset
+                .and_then(|c| encoding_rs::Encoding::for_label(c.as_bytes()))
+                .unwrap_or(encoding_rs::UTF_8);
+            let (text, _, _) = encoding.decode(&self.data);
+            return text.into_owned();
+        });
+    }
+
     /// Get reponse as JSON
     pub fn json(&self) -> serde_json::Value {
-        let value = serde_json::Value::from_str(self.data.as_str());
+        let value = serde_json::from_slice(&self.data);
         return value.unwrap_or(serde_json::Value::Null);
     }
 
-    /// Get reponse headers
+    /// Parse the JSON body into a typed value, such as a struct declared by [`crate::json::json`]
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, crate::json::Error> {
+        return serde_json::from_slice(&self.data);
+    }
+
+    /// Save the raw body into a file, returns 0 on success, -1 on error, see [`crate::fs::last_error`]
+    pub fn save<T: AsRef<str>>(&self, path: T) -> i32 {
+        let mut f = crate::fs::File::new();
+        if f.open(path, crate::fs::O_CREATE | crate::fs::O_TRUNCATE | crate::fs::O_WRITE) != 0 {
+            return crate::fs::fail(f.take_error());
+        }
+
+        if f.write_exact(&self.data) != 0 {
+            return crate::fs::fail(f.take_error());
+        }
+
+        return 0;
+    }
+
+    /// Get reponse headers, the repeated headers are joined by ", ", see [`Data::header_all`]
     pub fn headers(&self) -> &HashMap<String, String> {
         return &self.headers;
     }
+
+    /// Get the first value of the header by the case-insensitive name
+    pub fn header<T: AsRef<str>>(&self, name: T) -> Option<String> {
+        let value = self.raw.get(name.as_ref())?;
+        return Some(String::from_utf8_lossy(value.as_bytes()).into_owned());
+    }
+
+    /// Get all values of the header by the case-insensitive name, such as `Set-Cookie`
+    pub fn header_all<T: AsRef<str>>(&self, name: T) -> Vec<String> {
+        let values = self.raw.get_all(name.as_ref()).iter();
+        return values.map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned()).collect();
+    }
+
+    /// Get the raw response headers, the values may not be UTF-8
+    pub fn raw_headers(&self) -> &HeaderMap {
+        return &self.raw;
+    }
 }
 
 /// Most simple way to make a http get request, the gzip was enabled by default
 ///
+/// If no response was received, the status is [`TRANSPORT_ERROR`], see [`Data::error`]. It times out
+/// if not connected in 10 seconds, or the server stalls for 30 seconds, use a [`Request`] to change.
+///
 /// ```rust,no_run
 /// fn test() {
 ///     wsd::http::get("https://docs.rs/", |data| {
@@ -193,31 +584,21 @@ where
     URL: AsRef<str>,
     F: FnMut(Data),
 {
-    let g = || -> Result<Response, Box<dyn std::error::Error>> {
-        let client = Client::builder().gzip(true).build()?;
+    let g = || -> Result<Response, reqwest::Error> {
+        let client = Client::builder().gzip(true).connect_timeout(CONNECT_TIMEOUT).timeout(READ_TIMEOUT).build()?;
         let ret = client.get(url.as_ref()).send()?;
         return Ok(ret);
     };
 
-    // default as connection timed out
-    let mut data = Data {
-        status: 522,
-        data: "".to_string(),
-        headers: HashMap::new(),
-    };
-
-    if let Ok(response) = g() {
-        data.status = response.status().as_u16();
-        data.headers = get_headers(response.headers());
-        data.data = response.text().unwrap_or("".to_string());
-    }
-
     // result
-    f(data);
+    f(Data::complete(g()));
 }
 
 /// Most simple way to make a http post request, the gzip will be enabled if data greater than 1 KB.
 ///
+/// If no response was received, the status is [`TRANSPORT_ERROR`], see [`Data::error`]. It times out
+/// if not connected in 10 seconds, or the server stalls for 30 seconds, use a [`Request`] to change.
+///
 /// ```rust
 /// fn test() {
 ///     wsd::http::post("https://docs.rs/", "{id: 100}", |data| {
@@ -231,27 +612,53 @@ where
     BODY: Into<Body> + AsRef<[u8]>,
     F: FnMut(Data),
 {
-    let g = |x: BODY| -> Result<Response, Box<dyn std::error::Error>> {
+    let g = |x: BODY| -> Result<Response, reqwest::Error> {
         // enable zip if data reaches MTU (1300 - 1500)
         let zip = x.as_ref().len() > 1024;
-        let client = Client::builder().gzip(zip).build()?;
+        let client = Client::builder().gzip(zip).connect_timeout(CONNECT_TIMEOUT).timeout(READ_TIMEOUT).build()?;
         let ret = client.post(url.as_ref()).body(x).send()?;
         return Ok(ret);
     };
 
-    // default as connection timed out
-    let mut data = Data {
-        status: 522,
-        data: "".to_string(),
-        headers: HashMap::new(),
-    };
+    // result
+    f(Data::complete(g(body)));
+}
 
-    if let Ok(response) = g(body) {
-        data.status = response.status().as_u16();
-        data.headers = get_headers(response.headers());
-        data.data = response.text().unwrap_or("".to_string());
+/// Get the JSON of the url as a typed value, such as a struct declared by [`crate::json::json`]
+///
+/// A transport error or a status other than 2xx is returned as an I/O error.
+///
+/// ```rust,no_run
+/// use serde::{Deserialize, Serialize};
+/// use wsd::json::*;
+///
+/// json! { Crate {
+///     name: String,
+///     downloads: u64
+/// }}
+///
+/// fn test() {
+///     match wsd::http::get_json::<Crate>("https://example.com/crates/wsd") {
+///         Ok(c) => println!("{} = {}", c.name, c.downloads),
+///         Err(e) => println!("error = {}", e),
+///     }
+/// }
+/// ```
+pub fn get_json<T: DeserializeOwned>(url: impl AsRef<str>) -> Result<T, crate::json::Error> {
+    let mut c = Request::new(Method::GET, url);
+    c.gzip(true);
+    c.header("Accept", "application/json");
+
+    let mut result = Err(crate::json::Error::io(std::io::Error::other("no response")));
+    if c.send("", |data| {
+        result = match data.status() {
+            200..=299 => data.parse(),
+            status => Err(crate::json::Error::io(std::io::Error::other(format!("HTTP status {}", status)))),
+        };
+    }) != 0
+    {
+        return Err(crate::json::Error::io(std::io::Error::other(c.error().clone())));
     }
 
-    // result
-    f(data);
+    return result;
 }
@@ -0,0 +1,210 @@
+//! Stream large downloads into files, resuming the partial ones with HTTP range requests
+use super::*;
+use crate::fs::*;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use std::io::Read;
+
+/// Download the url into a file, see [`Request::download`]. There is no total timeout, but it
+/// fails if the server stalls for 10 seconds, use a [`Request`] to change. Returns 0 on success,
+/// -1 on error or cancelled.
+///
+/// ```rust,no_run
+/// fn test() {
+///     wsd::http::download("https://example.com/toolchain.tar.gz", "toolchain.tar.gz", |received, total| {
+///         println!("{}/{}", received, total);
+///         return true;
+///     });
+/// }
+/// ```
+pub fn download<URL, P, F>(url: URL, path: P, progress: F) -> i32
+where
+    URL: AsRef<str>,
+    P: AsRef<str>,
+    F: FnMut(u64, u64) -> bool,
+{
+    let mut c = Request::new(Method::GET, url);
+    c.gzip(true);
+    return c.download(path, progress);
+}
+
+// the start and total length of "bytes start-end/total"
+fn content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let (start, _) = span.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let total = total.trim().parse().ok();
+    return Some((start, total));
+}
+
+// the files go through the backend of the thread, as the partial file does
+
+// the content of a small file, empty if missing
+fn read_file(path: &str) -> String {
+    let mut f = File::new();
+    let mut data = Vec::new();
+    if f.open(path, O_READ) != 0 || f.read_to_end(&mut data) < 0 {
+        return String::new();
+    }
+    return String::from_utf8(data).unwrap_or_default();
+}
+
+// the length of a file, 0 if missing
+fn file_len(path: &str) -> u64 {
+    let mut f = File::new();
+    if f.open(path, O_READ) != 0 {
+        return 0;
+    }
+    return f.seek(0, SEEK_END).max(0) as u64;
+}
+
+fn write_file(path: &str, data: &str) -> i32 {
+    let mut f = File::new();
+    if f.open(path, O_CREATE | O_TRUNCATE | O_WRITE) != 0 {
+        return -1;
+    }
+    return f.write_exact(data);
+}
+
+impl Request {
+    /// Stream the response body into a file chunk by chunk, without buffering it in memory.
+    ///
+    /// The progress receives the bytes received and the total length, which is 0 if unknown,
+    /// return false to cancel. The body is written into `<path>.part` and renamed to the path
+    /// when completed, so the path never holds a partial file. A response other than 2xx is
+    /// an error. Returns 0 on success, -1 on error or cancelled, see [`Request::error`].
+    ///
+    /// If a `<path>.part` was left by a failed or cancelled download, it's resumed with a
+    /// `Range` request, validated by the `ETag` or `Last-Modified` of the first response
+    /// with `If-Range`. The download starts over if the server doesn't support ranges,
+    /// answers another range or the file was changed.
+    pub fn download<P, F>(&mut self, path: P, mut progress: F) -> i32
+    where
+        P: AsRef<str>,
+        F: FnMut(u64, u64) -> bool,
+    {
+        if self.client() != 0 {
+            return -1;
+        }
+
+        let temp = format!("{}.part", path.as_ref());
+        let validator = format!("{}.part.etag", path.as_ref());
+
+        // resume only if the partial file could be validated
+        let mut offset = 0;
+        let tag = read_file(&validator);
+        if !tag.is_empty() {
+            offset = file_len(&temp);
+        }
+
+        let deadline = self.deadline();
+        let mut x = self.build();
+        if offset > 0 {
+            x = x.header(RANGE, format!("bytes={}-", offset)).header(IF_RANGE, tag.as_str());
+        }
+
+        let mut response = match x.send() {
+            Ok(r) => r,
+            Err(e) => {
+                self.error = e.to_string();
+                return -1;
+            }
+        };
+
+        let status = response.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+            // stale partial file, start over
+            remove(&validator);
+            remove(&temp);
+            return self.download(path, progress);
+        }
+
+        if !status.is_success() {
+            self.error = format!("HTTP status {}", status);
+            return -1;
+        }
+
+        let mut total = response.content_length().unwrap_or(0);
+        let mut flags = O_CREATE | O_TRUNCATE | O_WRITE;
+        let range = response.headers().get(CONTENT_RANGE).and_then(|v| v.to_str().ok()).and_then(content_range);
+
+        match (status, range) {
+            (StatusCode::PARTIAL_CONTENT, Some((start, length))) if offset > 0 && start == offset => {
+                flags = O_CREATE | O_APPEND;
+                total = length.unwrap_or(if total > 0 { offset + total } else { 0 });
+            }
+            (StatusCode::PARTIAL_CONTENT, _) if offset > 0 => {
+                // not the requested range, start over
+                remove(&validator);
+                remove(&temp);
+                return self.download(path, progress);
+            }
+            (StatusCode::PARTIAL_CONTENT, _) => {
+                self.error = "unexpected partial content".to_string();
+                return -1;
+            }
+            _ => {
+                // a full body, keep the validator for resuming
+                offset = 0;
+                let headers = response.headers();
+                let tag = headers.get(ETAG).or_else(|| headers.get(LAST_MODIFIED));
+                let tag = tag.and_then(|v| v.to_str().ok()).unwrap_or("");
+                if tag.starts_with("W/") || write_file(&validator, tag) != 0 {
+                    remove(&validator);
+                }
+            }
+        }
+
+        let mut f = File::new();
+        if f.open(&temp, flags) != 0 {
+            self.error = f.error().to_string();
+            return -1;
+        }
+
+        let mut received = offset;
+        let mut buf = vec![0; 64 << 10];
+        loop {
+            let n = match response.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    // keep the partial file to resume
+                    self.error = e.to_string();
+                    return -1;
+                }
+            };
+
+            if f.write_exact(&buf[..n]) != 0 {
+                self.error = f.error().to_string();
+                return -1;
+            }
+
+            if deadline.is_some_and(|d| Instant::now() > d) {
+                self.error = "operation timed out".to_string();
+                return -1;
+            }
+
+            received += n as u64;
+            if !progress(received, total) {
+                self.error = "download cancelled".to_string();
+                return -1;
+            }
+        }
+
+        if f.sync() != 0 {
+            self.error = f.error().to_string();
+            return -1;
+        }
+        f.close();
+
+        if rename(&temp, path.as_ref()) != 0 {
+            self.error = last_error().to_string();
+            return -1;
+        }
+        remove(&validator);
+
+        return 0;
+    }
+}
@@ -0,0 +1,157 @@
+//! Build multipart/form-data bodies for uploading files
+use super::*;
+use crate::fs::File;
+use reqwest::blocking::multipart::{self, Part};
+use std::io::SeekFrom;
+
+// the length from the current position to the end
+fn remaining(handle: &mut dyn crate::fs::Handle) -> std::io::Result<u64> {
+    let pos = handle.stream_position()?;
+    let end = handle.seek(SeekFrom::End(0))?;
+    handle.seek(SeekFrom::Start(pos))?;
+    return Ok(end.saturating_sub(pos));
+}
+
+/// A multipart/form-data body, the file parts are streamed from the disk when sent
+///
+/// ```rust,no_run
+/// use wsd::http::*;
+///
+/// fn test() {
+///     let mut form = Form::new();
+///     form.text("name", "report");
+///     form.file("upload", "report.pdf", "application/pdf");
+///     form.bytes("note", "hello", "note.txt", "text/plain");
+///
+///     // the upload has to finish within the read timeout, disable it for large files
+///     let mut c = Request::new(Method::POST, "https://example.com/upload");
+///     c.read_timeout(0.0);
+///     c.send_form(form, |data| {
+///         println!("status = {}", data.status());
+///     });
+/// }
+/// ```
+pub struct Form {
+    inner: Option<multipart::Form>,
+    error: String,
+}
+
+impl Form {
+    /// Returns an empty form
+    pub fn new() -> Self {
+        return Form {
+            inner: Some(multipart::Form::new()),
+            error: "".into(),
+        };
+    }
+
+    /// Returns the first error of adding parts, the form can't be sent if any
+    pub fn error(&self) -> &String {
+        return &self.error;
+    }
+
+    /// Add a text field
+    pub fn text<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) -> &mut Self {
+        let name = name.into();
+        let value = value.into();
+        return self.part(name, Part::text(value), "", "");
+    }
+
+    /// Add a file part from the path, the filename is the last component of the path
+    pub fn file<K: Into<String>, P: AsRef<str>>(&mut self, name: K, path: P, content_type: &str) -> &mut Self {
+        let mut f = File::new();
+        if f.open(path.as_ref(), crate::fs::O_READ) != 0 {
+            if self.error.is_empty() {
+                self.error = format!("{}: {}", path.as_ref(), f.error());
+            }
+            return self;
+        }
+
+        let filename = std::path::Path::new(path.as_ref()).file_name();
+        let filename = filename.map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        return self.attach(name, f, &filename, content_type);
+    }
+
+    /// Add a file part from an opened file, streamed from the current position to the end
+    pub fn attach<K: Into<String>>(&mut self, name: K, mut file: File, filename: &str, content_type: &str) -> &mut Self {
+        let path = file.path().clone();
+        let mut handle = match file.take_handle() {
+            Some(h) => h,
+            None => {
+                if self.error.is_empty() {
+                    self.error = format!("{}: file not opened", path);
+                }
+                return self;
+            }
+        };
+
+        // the remaining length, so the body has a Content-Length
+        match remaining(handle.as_mut()) {
+            Ok(n) => {
+                return self.part(name.into(), Part::reader_with_length(handle, n), filename, content_type);
+            }
+            Err(e) => {
+                if self.error.is_empty() {
+                    self.error = format!("{}: {}", path, e);
+                }
+                return self;
+            }
+        }
+    }
+
+    /// Add a file part from bytes in memory
+    pub fn bytes<K: Into<String>, B: Into<Vec<u8>>>(&mut self, name: K, data: B, filename: &str, content_type: &str) -> &mut Self {
+        let data: Vec<u8> = data.into();
+        return self.part(name.into(), Part::bytes(data), filename, content_type);
+    }
+
+    // add a part with the optional filename and content type
+    fn part(&mut self, name: String, mut part: Part, filename: &str, content_type: &str) -> &mut Self {
+        if !filename.is_empty() {
+            part = part.file_name(filename.to_string());
+            if content_type.is_empty() {
+                part = part.mime_str("application/octet-stream").unwrap();
+            }
+        }
+
+        if !content_type.is_empty() {
+            match part.mime_str(content_type) {
+                Ok(p) => {
+                    part = p;
+                }
+                Err(e) => {
+                    if self.error.is_empty() {
+                        self.error = format!("{}: {}", content_type, e);
+                    }
+                    return self;
+                }
+            }
+        }
+
+        if let Some(form) = self.inner.take() {
+            self.inner = Some(form.part(name, part));
+        }
+
+        return self;
+    }
+}
+
+impl Request {
+    /// Send a multipart/form-data body, the `Content-Type` with the boundary is set by the form
+    ///
+    /// The whole body is sent within the read timeout, raise or disable it for large files, see
+    /// [`Request::read_timeout`].
+    pub fn send_form<F: FnMut(Data)>(&mut self, mut form: Form, f: F) -> i32 {
+        if !form.error.is_empty() {
+            self.error = form.error;
+            return -1;
+        }
+
+        if self.client() != 0 {
+            return -1;
+        }
+
+        let x = self.build().multipart(form.inner.take().unwrap());
+        return self.exec(x, f);
+    }
+}
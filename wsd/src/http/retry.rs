@@ -0,0 +1,218 @@
+//! Retry the failed requests with exponential backoff and jitter
+use super::*;
+use reqwest::header::RETRY_AFTER;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The retry policy of a [`Request`], see [`Request::retry`]
+///
+/// The connect errors, timeouts and the status 429, 502, 503 and 504 are retried by default,
+/// up to 3 attempts. The delay of the nth retry is `base * 2^(n-1)` seconds capped by `cap`,
+/// or the `Retry-After` of the response if any, also capped by `cap`. Only the idempotent
+/// methods are retried unless [`Retry::non_idempotent`] was set.
+///
+/// ```rust,no_run
+/// use wsd::http::*;
+///
+/// fn test() {
+///     let mut policy = Retry::new();
+///     policy.attempts(5).backoff(0.2, 10.0).status(500);
+///
+///     let mut c = Request::new(Method::GET, "https://docs.rs");
+///     c.retry(&policy);
+///     c.send("", |data| {
+///         println!("status = {}, attempts = {}", data.status(), data.attempts());
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Retry {
+    attempts: u32,
+    base: f32,
+    cap: f32,
+    jitter: bool,
+    statuses: Vec<u16>,
+    connect: bool,
+    timeout: bool,
+    non_idempotent: bool,
+}
+
+impl Retry {
+    /// Returns the default policy
+    pub fn new() -> Self {
+        return Retry {
+            attempts: 3,
+            base: 0.5,
+            cap: 30.0,
+            jitter: true,
+            statuses: vec![429, 502, 503, 504],
+            connect: true,
+            timeout: true,
+            non_idempotent: false,
+        };
+    }
+
+    /// Set the max attempts including the first one, 1 for no retry
+    pub fn attempts(&mut self, n: u32) -> &mut Self {
+        self.attempts = n.max(1);
+        return self;
+    }
+
+    /// Set the base delay and the max delay of the backoff as seconds
+    pub fn backoff(&mut self, base: f32, cap: f32) -> &mut Self {
+        self.base = base.max(0.0);
+        self.cap = cap.max(0.0);
+        return self;
+    }
+
+    /// Whether to randomize the delay between half and full of it, enabled by default
+    pub fn jitter(&mut self, enable: bool) -> &mut Self {
+        self.jitter = enable;
+        return self;
+    }
+
+    /// Retry the response status as well
+    pub fn status(&mut self, code: u16) -> &mut Self {
+        if !self.statuses.contains(&code) {
+            self.statuses.push(code);
+        }
+        return self;
+    }
+
+    /// Replace the retried response statuses
+    pub fn statuses(&mut self, codes: &[u16]) -> &mut Self {
+        self.statuses = codes.to_vec();
+        return self;
+    }
+
+    /// Whether to retry the connect errors
+    pub fn connect_errors(&mut self, enable: bool) -> &mut Self {
+        self.connect = enable;
+        return self;
+    }
+
+    /// Whether to retry the timeouts
+    pub fn timeouts(&mut self, enable: bool) -> &mut Self {
+        self.timeout = enable;
+        return self;
+    }
+
+    /// Whether to retry the non-idempotent methods such as POST and PATCH as well
+    pub fn non_idempotent(&mut self, enable: bool) -> &mut Self {
+        self.non_idempotent = enable;
+        return self;
+    }
+
+    // the delay before the nth retry
+    fn delay(&self, n: u32) -> Duration {
+        let mut seconds = (self.base * 2f32.powi(n.min(31) as i32 - 1)).min(self.cap);
+        if self.jitter {
+            seconds *= 0.5 + 0.5 * random();
+        }
+        return duration(seconds);
+    }
+}
+
+// the seconds as a duration, the infinite or too long as the max one
+fn duration(seconds: f32) -> Duration {
+    return Duration::try_from_secs_f32(seconds).unwrap_or(Duration::MAX);
+}
+
+// a random number in [0, 1)
+fn random() -> f32 {
+    let mut h = std::collections::hash_map::RandomState::new().build_hasher();
+    h.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos());
+    return (h.finish() >> 40) as f32 / (1u64 << 24) as f32;
+}
+
+fn idempotent(method: &Method) -> bool {
+    return matches!(*method, Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE);
+}
+
+// the Retry-After as delay seconds or a HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let date = http_date(value)?;
+    return Some(Duration::from_secs(date.saturating_sub(now)));
+}
+
+// the unix time of an IMF-fixdate, such as "Sun, 06 Nov 1994 08:49:37 GMT"
+fn http_date(s: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let fields: Vec<&str> = s.split_whitespace().collect();
+    if fields.len() != 6 || fields[5] != "GMT" {
+        return None;
+    }
+
+    let day: i64 = fields[1].parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == fields[2])? as i64 + 1;
+    let year: i64 = fields[3].parse().ok()?;
+    let time: Vec<i64> = fields[4].split(':').filter_map(|x| x.parse().ok()).collect();
+    if time.len() != 3 {
+        return None;
+    }
+
+    // days from the civil date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds = days * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+    return u64::try_from(seconds).ok();
+}
+
+impl Request {
+    /// Retry the failed requests by the policy, see [`Retry`]
+    ///
+    /// A request with a streamed body, such as a file part of a [`Form`], is never retried.
+    pub fn retry(&mut self, policy: &Retry) -> &mut Self {
+        self.retry = Some(policy.clone());
+        return self;
+    }
+
+    // send the request with the retries, returns the response and the attempts
+    pub(super) fn execute(&self, x: RequestBuilder) -> (Result<Response, reqwest::Error>, u32) {
+        let policy = match &self.retry {
+            Some(p) if p.non_idempotent || idempotent(&self.method) => p,
+            _ => return (x.send(), 1),
+        };
+
+        let mut n = 1;
+        loop {
+            let next = match x.try_clone() {
+                Some(next) if n < policy.attempts => next,
+                _ => return (x.send(), n),
+            };
+
+            let delay = match next.send() {
+                Ok(response) => {
+                    if !policy.statuses.contains(&response.status().as_u16()) {
+                        return (Ok(response), n);
+                    }
+                    match retry_after(response.headers()) {
+                        Some(d) => d.min(duration(policy.cap)),
+                        None => policy.delay(n),
+                    }
+                }
+                Err(e) => {
+                    if !(policy.connect && e.is_connect() || policy.timeout && e.is_timeout()) {
+                        return (Err(e), n);
+                    }
+                    policy.delay(n)
+                }
+            };
+
+            std::thread::sleep(delay);
+            n += 1;
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Percent-encoding, query strings and joining of urls
+//!
+//! ```rust
+//! use wsd::http::url;
+//!
+//! fn test() {
+//!     let query = url::encode_query([("q", "rust & c++"), ("page", "2")]);
+//!     assert!(query == "q=rust%20%26%20c%2B%2B&page=2");
+//!
+//!     let pairs = url::parse_query(&query);
+//!     assert!(pairs[0] == ("q".to_string(), "rust & c++".to_string()));
+//!
+//!     let link = url::join("https://docs.rs/wsd/latest/", "../0.1.0/wsd");
+//!     assert!(link == Some("https://docs.rs/wsd/0.1.0/wsd".to_string()));
+//! }
+//! ```
+pub use reqwest::Url;
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+// the unreserved characters of RFC 3986, which are never encoded
+fn unreserved(c: u8) -> bool {
+    return c.is_ascii_alphanumeric() || c == b'-' || c == b'.' || c == b'_' || c == b'~';
+}
+
+fn hex(c: u8) -> Option<u8> {
+    return (c as char).to_digit(16).map(|d| d as u8);
+}
+
+/// Percent-encode everything but the unreserved characters, for a path segment or a query key or value
+pub fn encode<T: AsRef<str>>(s: T) -> String {
+    let mut out = String::new();
+    for &c in s.as_ref().as_bytes() {
+        if unreserved(c) {
+            out.push(c as char);
+        } else {
+            out.push('%');
+            out.push(HEX[(c >> 4) as usize] as char);
+            out.push(HEX[(c & 15) as usize] as char);
+        }
+    }
+    return out;
+}
+
+/// Decode the percent-encoded string, the malformed escapes are kept as they are
+pub fn decode<T: AsRef<str>>(s: T) -> String {
+    let s = s.as_ref().as_bytes();
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'%' && i + 2 < s.len() {
+            if let (Some(h), Some(l)) = (hex(s[i + 1]), hex(s[i + 2])) {
+                out.push(h << 4 | l);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(s[i]);
+        i += 1;
+    }
+    return String::from_utf8_lossy(&out).into_owned();
+}
+
+/// Build a query string or an `application/x-www-form-urlencoded` body from the pairs
+pub fn encode_query<I, K, V>(pairs: I) -> String
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut out = String::new();
+    for (key, value) in pairs {
+        if !out.is_empty() {
+            out.push('&');
+        }
+        out.push_str(&encode(key));
+        out.push('=');
+        out.push_str(&encode(value));
+    }
+    return out;
+}
+
+/// Parse a query string or a form body into the decoded pairs, `+` is decoded as a space
+pub fn parse_query<T: AsRef<str>>(query: T) -> Vec<(String, String)> {
+    let query = query.as_ref();
+    let query = query.strip_prefix('?').unwrap_or(query);
+
+    let mut pairs = Vec::new();
+    for pair in query.split('&') {
+        if pair.is_empty() {
+            continue;
+        }
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        pairs.push((decode(key.replace('+', " ")), decode(value.replace('+', " "))));
+    }
+    return pairs;
+}
+
+/// Parse an absolute url, returns None if it's invalid
+pub fn parse<T: AsRef<str>>(url: T) -> Option<Url> {
+    return Url::parse(url.as_ref()).ok();
+}
+
+/// Resolve the reference against the base url, as a browser resolves a link
+pub fn join<B: AsRef<str>, R: AsRef<str>>(base: B, reference: R) -> Option<String> {
+    let url = parse(base)?.join(reference.as_ref()).ok()?;
+    return Some(url.to_string());
+}
+
+/// Append the encoded pairs to the query of the url, before the fragment
+pub fn with_query<T: AsRef<str>>(url: T, pairs: &[(String, String)]) -> String {
+    let url = url.as_ref();
+    if pairs.is_empty() {
+        return url.to_string();
+    }
+
+    let (head, fragment) = match url.find('#') {
+        Some(i) => url.split_at(i),
+        None => (url, ""),
+    };
+
+    let sep = if !head.contains('?') {
+        "?"
+    } else if head.ends_with('?') || head.ends_with('&') {
+        ""
+    } else {
+        "&"
+    };
+
+    return format!("{}{}{}{}", head, sep, encode_query(pairs.iter().map(|(k, v)| (k, v))), fragment);
+}
@@ -0,0 +1,51 @@
+#![cfg(feature = "async")]
+use wsd::fs::*;
+
+#[tokio::test]
+async fn test_async_file() {
+    let path = "test_async_file.txt";
+    remove(path);
+
+    let mut f = AsyncFile::new();
+    let mut n = f.open(path, O_CREATE | O_RW).await;
+    assert!(n == 0);
+
+    let data = "hello async world\n";
+    n = f.write(data).await;
+    assert!(n == data.len() as i32);
+    assert!(f.flush().await == 0);
+    assert!(f.position().await == data.len() as i64);
+
+    f.rewind().await;
+    let mut buf = [0; 64];
+    n = f.read(&mut buf).await;
+    assert!(data.as_bytes() == &buf[0..n as usize]);
+
+    f.seek(6, SEEK_SET).await;
+    let mut rest = Vec::new();
+    n = f.read_to_end(&mut rest).await;
+    assert!(rest == b"async world\n" && n == 12);
+
+    assert!(f.close().await == 0);
+    assert!(f.close().await != 0);
+    remove(path);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_async_close_error() {
+    // the write is buffered, no space left on device is reported by close()
+    let mut f = AsyncFile::new();
+    assert!(f.open("/dev/full", O_WRITE).await == 0);
+    assert!(f.write("data").await == 4);
+    assert!(f.close().await != 0);
+    assert!(f.error().raw_os_error() == Some(28));
+    assert!(f.is_none());
+}
+
+#[tokio::test]
+async fn test_async_open_failed() {
+    let mut f = AsyncFile::new();
+    assert!(f.open("test_async_open_failed", 0).await != 0);
+    println!("Expected error: {}", f.error());
+}
@@ -166,3 +166,717 @@ fn test_seek_and_read() {
     f.close();
     remove(path);
 }
+
+#[test]
+fn test_binary_encoding() {
+    let path = "test_binary_encoding.bin";
+    remove(path);
+
+    let mut f = File::new();
+    let mut n = f.open(path, O_CREATE | O_RW | O_TRUNCATE);
+    assert!(n == 0);
+
+    assert!(f.write_u8(0xAB) == 0);
+    assert!(f.write_u16_be(0x1234) == 0);
+    assert!(f.write_i32_le(-42) == 0);
+    assert!(f.write_u64_be(0x0102030405060708) == 0);
+    assert!(f.write_f64_le(3.5) == 0);
+    assert!(f.write_string("hello") == 0);
+    assert!(f.position() == 1 + 2 + 4 + 8 + 8 + 4 + 5);
+
+    f.rewind();
+    let mut buf = [0; 3];
+    n = f.read(&mut buf);
+    assert!(n == 3);
+    assert!(buf == [0xAB, 0x12, 0x34]);
+
+    f.rewind();
+    let mut a = 0u8;
+    let mut b = 0u16;
+    let mut c = 0i32;
+    let mut d = 0u64;
+    let mut e = 0f64;
+    let mut s = String::new();
+    assert!(f.read_u8(&mut a) == 0 && a == 0xAB);
+    assert!(f.read_u16_be(&mut b) == 0 && b == 0x1234);
+    assert!(f.read_i32_le(&mut c) == 0 && c == -42);
+    assert!(f.read_u64_be(&mut d) == 0 && d == 0x0102030405060708);
+    assert!(f.read_f64_le(&mut e) == 0 && e == 3.5);
+    assert!(f.read_string(&mut s) == 0 && s == "hello");
+
+    // short read
+    assert!(f.read_u32_le(&mut 0u32) != 0);
+    assert!(f.error().kind() == std::io::ErrorKind::UnexpectedEof);
+
+    // a corrupt length is a short read
+    let end = f.seek(0, SEEK_END);
+    assert!(f.write_u32_le(u32::MAX) == 0 && f.write_exact("abc") == 0);
+    f.seek(end, SEEK_SET);
+    let mut bytes = Vec::new();
+    assert!(f.read_bytes(&mut bytes) != 0);
+    assert!(f.error().kind() == std::io::ErrorKind::UnexpectedEof);
+
+    f.close();
+    remove(path);
+}
+
+#[test]
+fn test_memory_backend() {
+    use std::sync::Arc;
+
+    let memory = Arc::new(MemoryFs::new());
+    let path = "memory/test_memory_backend.txt";
+
+    {
+        let _guard = scoped_backend(memory.clone());
+
+        // parent directory is required
+        let mut f = File::new();
+        assert!(f.open(path, O_CREATE | O_RW) != 0);
+        assert!(mkdir("memory") == 0);
+
+        let mut n = f.open(path, O_CREATE | O_RW);
+        assert!(n == 0);
+
+        let data = "hello memory";
+        n = f.write(data);
+        assert!(n == data.len() as i32);
+
+        f.rewind();
+        let mut buf = [0; 32];
+        n = f.read(&mut buf);
+        assert!(data.as_bytes() == &buf[0..n as usize]);
+
+        // no space left on device
+        memory.inject(Fault::Write, 28);
+        assert!(f.write(data) == 0);
+        assert!(f.error().raw_os_error() == Some(28));
+        memory.clear_faults();
+        f.close();
+    }
+
+    // never touched the disk
+    assert!(!std::path::Path::new(path).exists());
+    assert!(memory.content(path).unwrap() == b"hello memory");
+
+    let _guard = scoped_backend(memory.clone());
+    assert!(remove(path) == 0);
+    assert!(!memory.exists(path));
+}
+
+#[test]
+fn test_follow_file() {
+    use std::thread;
+    use std::time::Duration;
+
+    let path = "test_follow_file.log";
+    let rotated = "test_follow_file.log.1";
+    remove(path);
+    remove(rotated);
+
+    let mut w = File::new();
+    assert!(w.open(path, O_CREATE | O_TRUNCATE | O_WRITE) == 0);
+    w.write("first\n");
+
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        w.write("sec");
+        thread::sleep(Duration::from_millis(100));
+        w.write("ond\n");
+        thread::sleep(Duration::from_millis(100));
+
+        // rotate the log
+        w.close();
+        std::fs::rename(path, rotated).unwrap();
+        w.open(path, O_CREATE | O_WRITE);
+        w.write("third\n");
+        w.close();
+    });
+
+    let mut lines = Vec::new();
+    let mut f = File::new();
+    assert!(f.open(path, O_READ) == 0);
+    let n = f.follow(|line| {
+        lines.push(line.to_string());
+        return lines.len() < 3;
+    });
+    assert!(n == 0);
+    assert!(lines == ["first", "second", "third"]);
+
+    writer.join().unwrap();
+    f.close();
+    remove(path);
+    remove(rotated);
+
+    // only the files on the disk are watched
+    let memory = std::sync::Arc::new(MemoryFs::new());
+    let _guard = scoped_backend(memory.clone());
+    assert!(f.open(path, O_CREATE | O_RW) == 0);
+    assert!(f.follow(|_| true) != 0);
+    assert!(f.error().kind() == std::io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn test_tar_archive() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = "test_tar_archive";
+    let _ = std::fs::remove_dir_all(root);
+    assert!(mkdir(format!("{}/src/sub", root)) == 0);
+
+    let script = format!("{}/src/sub/run.sh", root);
+    let mut f = File::new();
+    f.open(&script, O_CREATE | O_WRITE);
+    f.write("#!/bin/sh\necho hello\n");
+    f.close();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+    let archive = format!("{}/bundle.tar.gz", root);
+    let mut options = tar::Options::new();
+    options.gzip(true);
+    assert!(tar::create(&archive, &[format!("{}/src", root)], &options) == 0);
+
+    let out = format!("{}/out", root);
+    assert!(tar::extract(&archive, &out) == 0);
+
+    let copy = format!("{}/{}/src/sub/run.sh", out, root);
+    let meta = std::fs::metadata(&copy).unwrap();
+    assert!(meta.permissions().mode() & 0o777 == 0o750);
+    let mtime = |m: std::fs::Metadata| m.modified().unwrap().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert!(mtime(meta) == mtime(std::fs::metadata(&script).unwrap()));
+    assert!(std::fs::read_to_string(&copy).unwrap() == "#!/bin/sh\necho hello\n");
+
+    // path traversal
+    let evil = format!("{}/evil.tar", root);
+    {
+        let mut header = ::tar::Header::new_gnu();
+        let data = b"owned";
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.as_gnu_mut().unwrap().name[..12].copy_from_slice(b"../escape.tx");
+        header.set_cksum();
+        let mut b = ::tar::Builder::new(std::fs::File::create(&evil).unwrap());
+        b.append(&header, &data[..]).unwrap();
+        b.finish().unwrap();
+    }
+    assert!(tar::extract(&evil, &out) != 0);
+    println!("Expected error: {}", last_error());
+    assert!(!std::path::Path::new(&format!("{}/escape.tx", root)).exists());
+
+    // the mtime and mode of a read-only directory are restored after its files
+    let dir = format!("{}/old", root);
+    assert!(mkdir(&dir) == 0);
+    std::fs::write(format!("{}/data.txt", dir), "old").unwrap();
+    let old = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    std::fs::File::open(&dir).unwrap().set_modified(old).unwrap();
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+    let archive = format!("{}/old.tar", root);
+    assert!(tar::create(&archive, &[&dir], &tar::Options::new()) == 0);
+    let out = format!("{}/out_old", root);
+    assert!(tar::extract(&archive, &out) == 0);
+
+    let copy = format!("{}/{}/old", out, root);
+    let meta = std::fs::metadata(&copy).unwrap();
+    assert!(meta.permissions().mode() & 0o777 == 0o555);
+    assert!(meta.modified().unwrap() == old);
+    assert!(std::fs::read_to_string(format!("{}/data.txt", copy)).unwrap() == "old");
+
+    // ".." is stripped from the entry names
+    let archive = format!("{}/parent.tar", root);
+    assert!(tar::create(&archive, &[format!("../wsd/{}/src", root)], &tar::Options::new()) == 0);
+    let out = format!("{}/out_parent", root);
+    assert!(tar::extract(&archive, &out) == 0);
+    assert!(std::path::Path::new(&format!("{}/wsd/{}/src/sub/run.sh", out, root)).exists());
+
+    for d in [dir, copy] {
+        std::fs::set_permissions(d, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_csv() {
+    use serde::{Deserialize, Serialize};
+    use wsd::json::*;
+
+    json! { Student { name: String, age: u16, note: String } }
+
+    let path = "test_csv.csv";
+    remove(path);
+
+    let mut w = csv::Writer::new();
+    assert!(w.open(path, O_CREATE | O_TRUNCATE) == 0);
+    let john = Student { name: "John".to_string(), age: 18, note: "says \"hi\", twice".to_string() };
+    assert!(w.serialize(&john) == 0);
+    assert!(w.write(["Jack", "21", "multi\nline"]) == 0);
+    assert!(w.close() == 0);
+
+    let content = std::fs::read_to_string(path).unwrap();
+    assert!(content == "name,age,note\nJohn,18,\"says \"\"hi\"\", twice\"\nJack,21,\"multi\nline\"\n");
+
+    let mut r = csv::Reader::new();
+    assert!(r.open(path) == 0);
+    assert!(r.headers() == &["name", "age", "note"]);
+
+    let mut student = Student::new();
+    assert!(r.deserialize(&mut student) == 3);
+    assert!(student.name == "John" && student.age == 18 && student.note == john.note);
+
+    let mut row = Vec::new();
+    assert!(r.read(&mut row) == 3);
+    assert!(row == ["Jack", "21", "multi\nline"]);
+    assert!(r.read(&mut row) == 0);
+    r.close();
+
+    // custom delimiter without headers
+    let mut w = csv::Writer::new();
+    w.delimiter(b';').has_headers(false);
+    assert!(w.open(path, O_CREATE | O_TRUNCATE) == 0);
+    w.write(["a;b", "c"]);
+    w.close();
+
+    let mut r = csv::Reader::new();
+    r.delimiter(b';').has_headers(false);
+    assert!(r.open(path) == 0);
+    assert!(r.read(&mut row) == 2);
+    assert!(row == ["a;b", "c"]);
+
+    remove(path);
+}
+
+#[test]
+fn test_pid_file() {
+    let path = "test_pid_file.pid";
+    remove(path);
+
+    // stale pid of a crashed process
+    std::fs::write(path, "999999\n").unwrap();
+
+    let mut pid = PidFile::new();
+    assert!(pid.acquire(path) == 0);
+    assert!(PidFile::read(path) == std::process::id() as i32);
+
+    let mut other = PidFile::new();
+    assert!(other.acquire(path) != 0);
+    println!("Expected error: {}", other.error());
+
+    drop(pid);
+    assert!(!std::path::Path::new(path).exists());
+    assert!(other.acquire(path) == 0);
+    other.release();
+}
+
+#[test]
+fn test_named_mutex() {
+    let path = "test_named_mutex.lock";
+
+    let mut a = NamedMutex::new(path);
+    let mut b = NamedMutex::new(path);
+    assert!(a.lock(1.0) == 0);
+    assert!(b.try_lock() != 0);
+    assert!(b.lock(0.05) != 0);
+    assert!(b.error().kind() == std::io::ErrorKind::TimedOut);
+
+    let t = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        a.unlock();
+    });
+
+    assert!(b.lock(5.0) == 0);
+    assert!(b.is_locked());
+    t.join().unwrap();
+    b.unlock();
+
+    // the timeouts not representable as a duration wait forever
+    for timeout in [f32::INFINITY, f32::NAN, f32::MAX] {
+        assert!(b.lock(timeout) == 0);
+        b.unlock();
+    }
+    remove(path);
+}
+
+#[test]
+fn test_copy_file() {
+    let src = "test_copy_file.src";
+    let dst = "test_copy_file.dst";
+
+    let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    std::fs::write(src, &data).unwrap();
+
+    let mut calls = 0;
+    let n = copy_progress(src, dst, |copied, total| {
+        calls += 1;
+        assert!(copied <= total && total == data.len() as u64);
+    });
+    assert!(n == data.len() as i64);
+    assert!(calls > 0);
+    assert!(std::fs::read(dst).unwrap() == data);
+
+    // partial copy between opened files, appending uses the fallback
+    let mut a = File::new();
+    let mut b = File::new();
+    assert!(a.open(src, O_READ) == 0);
+    assert!(b.open(dst, O_CREATE | O_APPEND) == 0);
+    a.seek(10, SEEK_SET);
+    assert!(a.copy_to(&mut b, 20) == 20);
+    assert!(a.position() == 30);
+    a.close();
+    b.close();
+
+    let copied = std::fs::read(dst).unwrap();
+    assert!(copied.len() == data.len() + 20);
+    assert!(copied[data.len()..] == data[10..30]);
+
+    assert!(copy("test_copy_file.none", dst) < 0);
+    println!("Expected error: {}", last_error());
+
+    remove(src);
+    remove(dst);
+}
+
+#[test]
+fn test_chunks_and_split() {
+    let path = "test_chunks_and_split.txt";
+    let mut content = String::new();
+    for i in 0..1000 {
+        content.push_str(&format!("record {}\n", i));
+    }
+    std::fs::write(path, &content).unwrap();
+
+    let mut f = File::new();
+    assert!(f.open(path, O_READ) == 0);
+
+    let mut data = Vec::new();
+    let mut blocks = 0;
+    let mut chunks = f.chunks(1000);
+    while let Some(block) = chunks.next() {
+        assert!(block.len() == 1000 || data.len() + block.len() == content.len());
+        data.extend_from_slice(block);
+        blocks += 1;
+    }
+    assert!(chunks.error().is_none());
+    assert!(data == content.as_bytes());
+    assert!(blocks == content.len().div_ceil(1000));
+
+    f.seek(5, SEEK_SET);
+    let ranges = f.split(4);
+    assert!(f.position() == 5);
+    assert!(ranges.len() == 4);
+    assert!(ranges[0].start == 0 && ranges[3].end == content.len() as u64);
+    for (i, r) in ranges.iter().enumerate() {
+        assert!(content.as_bytes()[r.end as usize - 1] == b'\n');
+        if i > 0 {
+            assert!(ranges[i - 1].end == r.start);
+        }
+    }
+
+    // more workers than records
+    std::fs::write(path, "a\nb\n").unwrap();
+    assert!(f.split(10) == [0..2, 2..4]);
+
+    f.close();
+    remove(path);
+}
+
+#[test]
+fn test_dup_across_threads() {
+    fn assert_send<T: Send>() {}
+    assert_send::<File>();
+
+    let path = "test_dup_across_threads.txt";
+    remove(path);
+
+    let mut writer = File::new();
+    assert!(writer.open(path, O_CREATE | O_TRUNCATE | O_RW) == 0);
+    let mut reader = writer.dup().unwrap();
+    assert!(reader.path() == writer.path());
+
+    // the path is gone, the duplicates still work
+    remove(path);
+
+    let t = std::thread::spawn(move || {
+        for i in 0..100 {
+            assert!(writer.write(format!("{:04}\n", i)) == 5);
+        }
+        writer
+    });
+    let mut writer = t.join().unwrap();
+
+    // shared position
+    assert!(reader.position() == 500);
+
+    let mut buf = [0; 5];
+    assert!(reader.read_at(&mut buf, 495) == 5);
+    assert!(&buf == b"0099\n");
+    assert!(writer.write_at("XXXX", 0) == 4);
+    assert!(reader.read_at(&mut buf, 0) == 5);
+    assert!(&buf == b"XXXX\n");
+    assert!(reader.position() == 500);
+}
+
+#[test]
+fn test_disk_usage() {
+    let mut stat = FsStat::default();
+    assert!(statvfs(".", &mut stat) == 0);
+    assert!(stat.total > 0 && stat.available <= stat.free && stat.free <= stat.total);
+    assert!(statvfs("test_disk_usage.none", &mut stat) != 0);
+    println!("Expected error: {}", last_error());
+
+    let root = "test_disk_usage";
+    let _ = std::fs::remove_dir_all(root);
+    assert!(mkdir(format!("{}/sub", root)) == 0);
+    std::fs::write(format!("{}/a.txt", root), vec![1; 1000]).unwrap();
+    std::fs::write(format!("{}/sub/b.txt", root), vec![2; 3000]).unwrap();
+    std::fs::hard_link(format!("{}/a.txt", root), format!("{}/sub/link.txt", root)).unwrap();
+
+    let mut usage = DiskUsage::default();
+    assert!(du(root, &mut usage) == 0);
+    assert!(usage.files == 4);
+    let dirs = std::fs::metadata(root).unwrap().len() + std::fs::metadata(format!("{}/sub", root)).unwrap().len();
+    assert!(usage.apparent == dirs + 4000);
+
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_vectored_io() {
+    let path = "test_vectored_io.txt";
+
+    let mut f = File::new();
+    assert!(f.open(path, O_CREATE | O_TRUNCATE | O_RW) == 0);
+
+    let body = vec![b'x'; 100_000];
+    let n = f.writev(&[b"HEAD", b"", &body, b"TAIL"]);
+    assert!(n == 100_008);
+
+    f.rewind();
+    let mut head = [0; 4];
+    let mut rest = vec![0; 100_004];
+    let n = f.readv(&mut [&mut head, &mut rest]);
+    assert!(n == 100_008);
+    assert!(&head == b"HEAD");
+    assert!(rest[..100_000] == body[..] && &rest[100_000..] == b"TAIL");
+
+    f.seek(-4, SEEK_END);
+    let mut tail = [0; 4];
+    assert!(f.readv(&mut [&mut tail]) == 4);
+    assert!(&tail == b"TAIL");
+
+    f.close();
+    remove(path);
+
+    // the in-memory backend fills one buffer per call
+    let memory = std::sync::Arc::new(MemoryFs::new());
+    let _guard = scoped_backend(memory.clone());
+    let mut f = File::new();
+    assert!(f.open(path, O_CREATE | O_RW) == 0);
+    assert!(f.writev(&[b"HEAD", b"BODY", b"TAIL"]) == 12);
+
+    f.rewind();
+    let (mut a, mut b, mut c) = ([0; 4], [0; 4], [0; 8]);
+    assert!(f.readv(&mut [&mut a, &mut b, &mut c]) == 12);
+    assert!(&a == b"HEAD" && &b == b"BODY" && &c[..4] == b"TAIL");
+
+    // a single call if nonblocking
+    let mut f = File::new();
+    assert!(f.open(path, O_READ | O_NONBLOCK) == 0);
+    let (mut a, mut b) = ([0; 4], [0; 4]);
+    assert!(f.readv(&mut [&mut a, &mut b]) == 4);
+    assert!(&a == b"HEAD");
+}
+
+#[test]
+fn test_xattr() {
+    let path = "test_xattr.bin";
+    std::fs::write(path, "cached").unwrap();
+
+    if setxattr(path, "user.source", "https://docs.rs") != 0 {
+        // the file system doesn't support xattrs
+        assert!(last_error().kind() == std::io::ErrorKind::Unsupported);
+        remove(path);
+        return;
+    }
+
+    let mut value = Vec::new();
+    assert!(getxattr(path, "user.source", &mut value) == 15);
+    assert!(value == b"https://docs.rs");
+
+    let mut f = File::new();
+    assert!(f.open(path, O_RW) == 0);
+    assert!(f.setxattr("user.checksum", "abc") == 0);
+
+    let mut names = Vec::new();
+    assert!(f.listxattr(&mut names) >= 2);
+    assert!(names.contains(&"user.source".to_string()) && names.contains(&"user.checksum".to_string()));
+
+    assert!(f.removexattr("user.checksum") == 0);
+    assert!(f.getxattr("user.checksum", &mut value) < 0);
+    println!("Expected error: {}", f.error());
+
+    assert!(removexattr(path, "user.source") == 0);
+    assert!(listxattr(path, &mut names) >= 0);
+    assert!(!names.contains(&"user.source".to_string()));
+
+    f.close();
+    remove(path);
+}
+
+#[test]
+fn test_sync_dir() {
+    let root = "test_sync_dir";
+    let src = format!("{}/src", root);
+    let dst = format!("{}/dst", root);
+    let _ = std::fs::remove_dir_all(root);
+
+    assert!(mkdir(format!("{}/sub", src)) == 0);
+    std::fs::write(format!("{}/a.txt", src), "aaa").unwrap();
+    std::fs::write(format!("{}/sub/b.txt", src), "bbb").unwrap();
+    std::fs::write(format!("{}/sub/skip.tmp", src), "tmp").unwrap();
+
+    let mut options = SyncOptions::new();
+    options.exclude("*.tmp");
+
+    // dry run
+    options.dry_run(true);
+    let mut changes = Vec::new();
+    let n = sync_dir_progress(&src, &dst, &options, |action, path| {
+        changes.push((action, path.to_string_lossy().to_string()));
+    });
+    assert!(n == 4);
+    assert!(changes[1] == (SyncAction::Create, "a.txt".to_string()));
+    assert!(!std::path::Path::new(&dst).exists());
+
+    options.dry_run(false);
+    assert!(sync_dir(&src, &dst, &options) == 4);
+    assert!(std::fs::read_to_string(format!("{}/sub/b.txt", dst)).unwrap() == "bbb");
+    assert!(!std::path::Path::new(&format!("{}/sub/skip.tmp", dst)).exists());
+
+    // nothing changed
+    assert!(sync_dir(&src, &dst, &options) == 0);
+
+    // update and delete
+    std::fs::write(format!("{}/a.txt", src), "aaaa").unwrap();
+    std::fs::write(format!("{}/extra.txt", dst), "x").unwrap();
+    std::fs::write(format!("{}/keep.tmp", dst), "x").unwrap();
+    options.delete(true);
+    let mut changes = Vec::new();
+    let n = sync_dir_progress(&src, &dst, &options, |action, path| {
+        changes.push((action, path.to_string_lossy().to_string()));
+    });
+    assert!(n == 2);
+    assert!(changes == [(SyncAction::Update, "a.txt".to_string()), (SyncAction::Delete, "extra.txt".to_string())]);
+    assert!(std::path::Path::new(&format!("{}/keep.tmp", dst)).exists());
+
+    // same size and mtime, but different content
+    std::fs::write(format!("{}/a.txt", dst), "bbbb").unwrap();
+    let mtime = std::fs::metadata(format!("{}/a.txt", src)).unwrap().modified().unwrap();
+    std::fs::File::options().write(true).open(format!("{}/a.txt", dst)).unwrap().set_modified(mtime).unwrap();
+    assert!(sync_dir(&src, &dst, &options) == 0);
+    options.checksum(true);
+    assert!(sync_dir(&src, &dst, &options) == 1);
+    assert!(std::fs::read_to_string(format!("{}/a.txt", dst)).unwrap() == "aaaa");
+
+    // read-only files are created and updated
+    use std::os::unix::fs::PermissionsExt;
+    let readonly = format!("{}/readonly.txt", src);
+    std::fs::write(&readonly, "r1").unwrap();
+    std::fs::set_permissions(&readonly, std::fs::Permissions::from_mode(0o444)).unwrap();
+    assert!(sync_dir(&src, &dst, &options) == 1);
+    let copy = format!("{}/readonly.txt", dst);
+    assert!(std::fs::metadata(&copy).unwrap().permissions().mode() & 0o777 == 0o444);
+    assert!(sync_dir(&src, &dst, &options) == 0);
+
+    std::fs::set_permissions(&readonly, std::fs::Permissions::from_mode(0o644)).unwrap();
+    std::fs::write(&readonly, "r2!").unwrap();
+    std::fs::set_permissions(&readonly, std::fs::Permissions::from_mode(0o444)).unwrap();
+    assert!(sync_dir(&src, &dst, &options) == 1);
+    assert!(std::fs::read_to_string(&copy).unwrap() == "r2!");
+
+    // non-UTF-8 file names
+    use std::os::unix::ffi::OsStrExt;
+    let name = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+    std::fs::write(std::path::Path::new(&src).join(name), "latin").unwrap();
+    assert!(sync_dir(&src, &dst, &options) == 1);
+    assert!(std::fs::read(std::path::Path::new(&dst).join(name)).unwrap() == b"latin");
+
+    // on the disk whatever the backend
+    let memory = std::sync::Arc::new(MemoryFs::new());
+    let _guard = scoped_backend(memory.clone());
+    std::fs::write(format!("{}/c.txt", src), "ccc").unwrap();
+    assert!(sync_dir(&src, &dst, &options) == 1);
+    assert!(std::fs::read_to_string(format!("{}/c.txt", dst)).unwrap() == "ccc");
+    assert!(!memory.exists(format!("{}/c.txt", dst)));
+
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[test]
+fn test_store() {
+    use serde::{Deserialize, Serialize};
+    use wsd::json::*;
+
+    json! { Profile { name: String, age: u16 } }
+
+    let path = "test_store.db";
+    remove(path);
+
+    {
+        let mut store = Store::new();
+        assert!(store.open(path) == 0);
+        assert!(store.set("greeting", "hello") == 0);
+        assert!(store.set("count", &1) == 0);
+        assert!(store.set("count", &2) == 0);
+        assert!(store.set("john", &Profile { name: "John".to_string(), age: 18 }) == 0);
+        assert!(store.set("temp", &true) == 0);
+        assert!(store.delete("temp") == 0);
+        assert!(store.delete("temp") != 0);
+        assert!(store.records() == 6);
+    }
+
+    // crash in the middle of a record
+    let mut f = File::new();
+    f.open(path, O_APPEND);
+    f.write([40, 0, 0, 0, 1, 2]);
+    f.close();
+
+    let mut store = Store::new();
+    assert!(store.open(path) == 0);
+    assert!(store.len() == 3 && store.records() == 6);
+
+    let mut greeting = String::new();
+    let mut count = 0;
+    let mut john = Profile::new();
+    assert!(store.get("greeting", &mut greeting) == 0 && greeting == "hello");
+    assert!(store.get("count", &mut count) == 0 && count == 2);
+    assert!(store.get("john", &mut john) == 0 && john.age == 18);
+    assert!(store.get("temp", &mut count) != 0);
+
+    assert!(store.compact() == 0);
+    assert!(store.records() == 3);
+    assert!(store.set("count", &3) == 0);
+    store.close();
+
+    assert!(store.open(path) == 0);
+    assert!(store.get("count", &mut count) == 0 && count == 3);
+    assert!(store.records() == 4);
+    store.close();
+
+    remove(path);
+
+    // a failed append is cut off, the later records survive the reopen
+    let memory = std::sync::Arc::new(MemoryFs::new());
+    let _guard = scoped_backend(memory.clone());
+    assert!(store.open(path) == 0);
+    assert!(store.set("a", &1) == 0);
+    memory.inject(Fault::Flush, 5);
+    assert!(store.set("b", &2) != 0);
+    assert!(store.error().raw_os_error() == Some(5));
+    memory.clear_faults();
+    assert!(store.set("c", &3) == 0);
+    store.close();
+
+    assert!(store.open(path) == 0);
+    assert!(store.records() == 2);
+    assert!(store.contains("a") && !store.contains("b") && store.contains("c"));
+}
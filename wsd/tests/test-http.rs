@@ -0,0 +1,548 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use wsd::http::*;
+use wsd::json::json;
+use serde::{Deserialize, Serialize};
+
+// serve the requests on a local port by the handler, which receives the request head and body
+fn serve<F>(mut handler: F) -> String
+where
+    F: FnMut(&str, &[u8]) -> Vec<u8> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+
+            let length = head
+                .lines()
+                .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                .map(|v| v.parse::<usize>().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            let _ = reader.read_exact(&mut body);
+
+            let response = handler(&head, &body);
+            let _ = stream.write_all(&response);
+        }
+    });
+
+    return url;
+}
+
+// a complete response with the body
+fn response(status: &str, headers: &[&str], body: &[u8]) -> Vec<u8> {
+    let mut r = format!("HTTP/1.1 {}\r\nConnection: close\r\nContent-Length: {}\r\n", status, body.len());
+    for h in headers {
+        r.push_str(h);
+        r.push_str("\r\n");
+    }
+    r.push_str("\r\n");
+
+    let mut r = r.into_bytes();
+    r.extend_from_slice(body);
+    return r;
+}
+
+#[test]
+fn test_binary_body() {
+    let png = vec![0x89, b'P', b'N', b'G', 0xff, 0x00, 0xfe];
+    let body = png.clone();
+    let url = serve(move |head, _| {
+        if head.starts_with("GET /latin1") {
+            return response("200 OK", &["Content-Type: text/plain; charset=ISO-8859-1"], b"caf\xe9");
+        }
+        return response("200 OK", &["Content-Type: image/png"], &body);
+    });
+
+    let mut bytes = Vec::new();
+    get(format!("{}/image.png", url), |data| {
+        assert!(data.status() == 200);
+        bytes = data.bytes().to_vec();
+
+        let path = "test_binary_body.png";
+        assert!(data.save(path) == 0);
+        assert!(std::fs::read(path).unwrap() == png);
+        wsd::fs::remove(path);
+    });
+    assert!(bytes == png);
+
+    let mut c = Request::new(Method::GET, format!("{}/latin1", url));
+    let ret = c.send("", |data| {
+        // decoded once, shared by the threads
+        std::thread::scope(|s| {
+            s.spawn(|| assert!(data.text() == "café"));
+            s.spawn(|| assert!(data.text() == "café"));
+        });
+        assert!(data.bytes() == b"caf\xe9");
+    });
+    assert!(ret == 0);
+}
+
+#[test]
+fn test_download() {
+    let body: Vec<u8> = (0..300_000).map(|i| (i % 253) as u8).collect();
+    let expected = body.clone();
+    let url = serve(move |head, _| {
+        if head.starts_with("GET /missing") {
+            return response("404 Not Found", &[], b"");
+        }
+        return response("200 OK", &["Content-Type: application/octet-stream"], &body);
+    });
+
+    let path = "test_download.bin";
+    wsd::fs::remove(path);
+
+    let mut calls = 0;
+    let mut last = (0, 0);
+    let ret = download(format!("{}/file.bin", url), path, |received, total| {
+        calls += 1;
+        last = (received, total);
+        return true;
+    });
+    assert!(ret == 0);
+    assert!(calls > 0 && last == (300_000, 300_000));
+    assert!(std::fs::read(path).unwrap() == expected);
+    assert!(!std::path::Path::new("test_download.bin.part").exists());
+
+    // cancelled, the existing file is untouched
+    let mut c = Request::new(Method::GET, format!("{}/file.bin", url));
+    assert!(c.download(path, |received, _| received < 100_000) != 0);
+    assert!(c.error() == "download cancelled");
+    assert!(std::fs::read(path).unwrap() == expected);
+    wsd::fs::remove("test_download.bin.part");
+    wsd::fs::remove("test_download.bin.part.etag");
+
+    let mut c = Request::new(Method::GET, format!("{}/missing", url));
+    assert!(c.download(path, |_, _| true) != 0);
+    println!("Expected error: {}", c.error());
+
+    wsd::fs::remove(path);
+}
+
+#[test]
+fn test_resume_download() {
+    let body: Vec<u8> = (0..200_000).map(|i| (i % 241) as u8).collect();
+    let expected = body.clone();
+    let url = serve(move |head, _| {
+        let lower = head.to_ascii_lowercase();
+        let range = lower.lines().find_map(|l| l.strip_prefix("range: bytes=").map(|v| v.trim_end_matches('-').to_string()));
+        let etag = if head.starts_with("GET /changed") { "\"v2\"" } else { "\"v1\"" };
+        let matched = lower.contains(&format!("if-range: {}", etag));
+        if head.starts_with("GET /norange") || range.is_none() || !matched {
+            return response("200 OK", &[&format!("ETag: {}", etag)], &body);
+        }
+
+        // a range other than the requested one
+        let start: usize = if head.starts_with("GET /mismatch") { 0 } else { range.unwrap().parse().unwrap() };
+        let content_range = format!("Content-Range: bytes {}-{}/{}", start, body.len() - 1, body.len());
+        return response("206 Partial Content", &[&content_range, &format!("ETag: {}", etag)], &body[start..]);
+    });
+
+    let path = "test_resume_download.bin";
+    let part = "test_resume_download.bin.part";
+
+    for (name, resumed) in [("file", true), ("norange", false), ("changed", false), ("mismatch", false)] {
+        wsd::fs::remove(path);
+        wsd::fs::remove(part);
+
+        // cancel in the middle
+        let mut c = Request::new(Method::GET, format!("{}/{}", url, if name == "changed" { "file" } else { name }));
+        assert!(c.download(path, |received, _| received < 50_000) != 0);
+        let partial = std::fs::metadata(part).unwrap().len();
+        assert!((50_000..200_000).contains(&partial));
+
+        let mut first = 0;
+        let mut c = Request::new(Method::GET, format!("{}/{}", url, name));
+        let ret = c.download(path, |received, total| {
+            if first == 0 {
+                first = received;
+            }
+            assert!(total == 200_000);
+            return true;
+        });
+        assert!(ret == 0);
+        assert!((first > partial) == resumed);
+        assert!(std::fs::read(path).unwrap() == expected);
+        assert!(!std::path::Path::new(part).exists());
+        assert!(!std::path::Path::new("test_resume_download.bin.part.etag").exists());
+    }
+
+    wsd::fs::remove(path);
+
+    // every file goes through the backend of the thread
+    let memory = std::sync::Arc::new(wsd::fs::MemoryFs::new());
+    let _guard = wsd::fs::scoped_backend(memory.clone());
+
+    let mut c = Request::new(Method::GET, format!("{}/file", url));
+    assert!(c.download(path, |received, _| received < 50_000) != 0);
+    let partial = memory.content(part).unwrap().len() as u64;
+    assert!(memory.content("test_resume_download.bin.part.etag").unwrap() == b"\"v1\"");
+
+    let mut first = 0;
+    assert!(c.download(path, |received, _| {
+        if first == 0 {
+            first = received;
+        }
+        return true;
+    }) == 0);
+    assert!(first > partial);
+    assert!(memory.content(path).unwrap() == expected);
+    assert!(!memory.exists(part) && !memory.exists("test_resume_download.bin.part.etag"));
+    assert!(!std::path::Path::new(path).exists() && !std::path::Path::new(part).exists());
+}
+
+#[test]
+fn test_form() {
+    let url = serve(|head, body| {
+        let echo = format!("{}\r\n{}", head, String::from_utf8_lossy(body));
+        return response("200 OK", &[], echo.as_bytes());
+    });
+
+    let path = "test_form.txt";
+    std::fs::write(path, "file content").unwrap();
+
+    let mut f = wsd::fs::File::new();
+    f.open(path, wsd::fs::O_READ);
+    f.seek(5, wsd::fs::SEEK_SET);
+
+    let mut form = Form::new();
+    form.text("name", "wsd");
+    form.file("upload", path, "text/plain");
+    form.attach("rest", f, "rest.txt", "");
+    form.bytes("raw", vec![1, 2, 3], "raw.bin", "application/x-raw");
+    assert!(form.error().is_empty());
+
+    let mut text = String::new();
+    let mut c = Request::new(Method::POST, &url);
+    assert!(c.send_form(form, |data| text = data.text().clone()) == 0);
+
+    assert!(text.to_ascii_lowercase().contains("content-type: multipart/form-data; boundary="));
+    assert!(text.contains("name=\"name\"\r\n\r\nwsd\r\n"));
+    assert!(text.contains("name=\"upload\"; filename=\"test_form.txt\"\r\nContent-Type: text/plain\r\n\r\nfile content\r\n"));
+    assert!(text.contains("filename=\"rest.txt\"\r\nContent-Type: application/octet-stream\r\n\r\ncontent\r\n"));
+    assert!(text.contains("filename=\"raw.bin\"\r\nContent-Type: application/x-raw\r\n\r\n\x01\x02\x03\r\n"));
+
+    // a missing file fails the form
+    let mut form = Form::new();
+    form.file("upload", "test_form_missing.txt", "");
+    assert!(!form.error().is_empty());
+    assert!(c.send_form(form, |_| {}) != 0);
+
+    wsd::fs::remove(path);
+}
+
+#[test]
+fn test_url() {
+    assert!(url::encode("a b&c=d/é~") == "a%20b%26c%3Dd%2F%C3%A9~");
+    assert!(url::decode("a%20b%26c%3Dd%2F%C3%A9~") == "a b&c=d/é~");
+    assert!(url::decode("100%") == "100%");
+    assert!(url::decode("%zz") == "%zz");
+
+    let pairs = url::parse_query("?a=1&b=x+y&c&d=%3D");
+    assert!(pairs.len() == 4);
+    assert!(pairs[1] == ("b".to_string(), "x y".to_string()));
+    assert!(pairs[2] == ("c".to_string(), "".to_string()));
+    assert!(pairs[3] == ("d".to_string(), "=".to_string()));
+
+    assert!(url::join("https://docs.rs/wsd/latest/", "../0.1.0/wsd").unwrap() == "https://docs.rs/wsd/0.1.0/wsd");
+    assert!(url::join("https://docs.rs/wsd/", "/crates").unwrap() == "https://docs.rs/crates");
+    assert!(url::join("not a url", "x").is_none());
+    assert!(url::parse("https://docs.rs:8080/a?b=c").unwrap().port() == Some(8080));
+
+    let pairs = vec![("q".to_string(), "a&b".to_string())];
+    assert!(url::with_query("https://x.com/p#top", &pairs) == "https://x.com/p?q=a%26b#top");
+    assert!(url::with_query("https://x.com/p?id=1", &pairs) == "https://x.com/p?id=1&q=a%26b");
+}
+
+#[test]
+fn test_query_form() {
+    let url = serve(|head, body| {
+        let echo = format!("{}\r\n{}", head, String::from_utf8_lossy(body));
+        return response("200 OK", &[], echo.as_bytes());
+    });
+
+    let mut text = String::new();
+    let mut c = Request::new(Method::GET, format!("{}/search?lang=en", url));
+    c.query("q", "rust & c++").query("page", "2");
+    assert!(c.send("", |data| text = data.text().clone()) == 0);
+    assert!(text.starts_with("GET /search?lang=en&q=rust%20%26%20c%2B%2B&page=2 HTTP/1.1"));
+
+    let mut c = Request::new(Method::POST, format!("{}/login", url));
+    assert!(c.form([("user", "wsd"), ("password", "a=b&c")], |data| text = data.text().clone()) == 0);
+    assert!(text.to_ascii_lowercase().contains("content-type: application/x-www-form-urlencoded"));
+    assert!(text.ends_with("\r\nuser=wsd&password=a%3Db%26c"));
+}
+
+#[test]
+fn test_retry() {
+    let mut count = 0;
+    let url = serve(move |head, _| {
+        if head.starts_with("GET /reset") {
+            count = 0;
+            return response("200 OK", &[], b"");
+        }
+
+        // unavailable twice, then ok
+        count += 1;
+        if count <= 2 {
+            let after = if count == 1 { "Retry-After: 0" } else { "Retry-After: Sun, 06 Nov 1994 08:49:37 GMT" };
+            return response("503 Service Unavailable", &[after], b"busy");
+        }
+        return response("200 OK", &[], b"ok");
+    });
+
+    let reset = || {
+        let mut c = Request::new(Method::GET, format!("{}/reset", url));
+        assert!(c.send("", |_| {}) == 0);
+    };
+
+    let mut policy = Retry::new();
+    policy.backoff(0.01, 0.1).jitter(false);
+
+    // retried until ok
+    let mut c = Request::new(Method::GET, &url);
+    c.retry(&policy);
+    let mut result = (0, 0);
+    assert!(c.send("", |data| result = (data.status(), data.attempts())) == 0);
+    assert!(result == (200, 3));
+
+    // out of attempts, the last response is returned
+    reset();
+    policy.attempts(2);
+    let mut c = Request::new(Method::GET, &url);
+    c.retry(&policy);
+    assert!(c.send("", |data| result = (data.status(), data.attempts())) == 0);
+    assert!(result == (503, 2));
+
+    // POST is retried only if allowed
+    reset();
+    let mut c = Request::new(Method::POST, &url);
+    c.retry(&policy);
+    assert!(c.send("data", |data| result = (data.status(), data.attempts())) == 0);
+    assert!(result == (503, 1));
+
+    reset();
+    policy.attempts(3).non_idempotent(true);
+    let mut c = Request::new(Method::POST, &url);
+    c.retry(&policy);
+    assert!(c.send("data", |data| result = (data.status(), data.attempts())) == 0);
+    assert!(result == (200, 3));
+
+    // no cap on the Retry-After
+    reset();
+    policy.backoff(0.01, f32::INFINITY);
+    let mut c = Request::new(Method::GET, &url);
+    c.retry(&policy);
+    assert!(c.send("", |data| result = (data.status(), data.attempts())) == 0);
+    assert!(result == (200, 3));
+    policy.backoff(0.01, 0.1);
+
+    // connect errors
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut c = Request::new(Method::GET, format!("http://{}", closed));
+    c.retry(&policy);
+    let start = std::time::Instant::now();
+    assert!(c.send("", |_| {}) != 0);
+    assert!(start.elapsed() >= std::time::Duration::from_millis(30));
+}
+
+json! { Account {
+    name: String,
+    age: u32,
+    tags: [String]
+}}
+
+#[test]
+fn test_json() {
+    let url = serve(|head, body| {
+        if head.starts_with("GET /missing") {
+            return response("404 Not Found", &[], b"{}");
+        }
+        if head.starts_with("GET /broken") {
+            return response("200 OK", &[], b"{\"name\": 1}");
+        }
+        if head.starts_with("GET") {
+            return response("200 OK", &["Content-Type: application/json"], br#"{"name":"wsd","age":3,"tags":["a","b"]}"#);
+        }
+
+        // echo the content type and body
+        let lower = head.to_ascii_lowercase();
+        assert!(lower.contains("content-type: application/json"));
+        return response("200 OK", &[], body);
+    });
+
+    let account: Account = get_json(format!("{}/account", url)).unwrap();
+    assert!(account.name == "wsd" && account.age == 3 && account.tags == ["a", "b"]);
+
+    assert!(get_json::<Account>(format!("{}/missing", url)).unwrap_err().to_string().contains("404"));
+    assert!(get_json::<Account>(format!("{}/broken", url)).is_err());
+
+    let mut echo = Account::new();
+    let mut c = Request::new(Method::POST, &url);
+    assert!(c.send_json(&account, |data| echo = data.parse().unwrap()) == 0);
+    assert!(echo.name == "wsd" && echo.tags.len() == 2);
+
+    let mut c = Request::new(Method::GET, format!("{}/broken", url));
+    assert!(c.send("", |data| assert!(data.parse::<Account>().is_err())) == 0);
+}
+
+#[test]
+fn test_transport_error() {
+    // a real 522 from the server is not a transport error
+    let url = serve(|_, _| response("522 Connection Timed Out", &[], b"origin"));
+    let mut result = (1, String::new(), ErrorKind::Other);
+    get(&url, |data| result = (data.status(), data.error().clone(), data.kind()));
+    assert!(result == (522, "".to_string(), ErrorKind::None));
+
+    // refused
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    get(format!("http://{}", closed), |data| {
+        assert!(data.status() == TRANSPORT_ERROR);
+        assert!(data.kind() == ErrorKind::Connect && data.is_connect() && !data.is_timeout());
+        assert!(!data.error().is_empty() && data.bytes().is_empty());
+    });
+
+    post(format!("http://{}", closed), "data", |data| {
+        assert!(data.status() == TRANSPORT_ERROR && data.kind() == ErrorKind::Connect);
+    });
+
+    // unresolvable
+    get("http://wsd.invalid/", |data| {
+        assert!(data.status() == TRANSPORT_ERROR && data.kind() == ErrorKind::Dns);
+    });
+
+    // invalid url
+    get("not a url", |data| {
+        assert!(data.status() == TRANSPORT_ERROR && data.kind() == ErrorKind::Builder);
+    });
+}
+
+#[test]
+fn test_headers() {
+    let url = serve(|head, _| {
+        let mut r = response("200 OK", &["Set-Cookie: a=1", "Set-Cookie: b=2", "X-Request: echo"], head.as_bytes());
+        // insert a non-UTF-8 header before the blank line
+        let at = r.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 2;
+        r.splice(at..at, b"X-Latin: caf\xe9\r\n".iter().cloned());
+        return r;
+    });
+
+    let mut c = Request::new(Method::GET, &url);
+    c.header("Accept", "text/plain").header("Accept", "text/html");
+    let ret = c.send("", |data| {
+        assert!(data.header("set-cookie").unwrap() == "a=1");
+        assert!(data.header_all("SET-COOKIE") == ["a=1", "b=2"]);
+        assert!(data.headers()["set-cookie"] == "a=1, b=2");
+        assert!(data.header("X-REQUEST").unwrap() == "echo");
+        assert!(data.header("x-missing").is_none() && data.header_all("x-missing").is_empty());
+
+        // non-UTF-8
+        assert!(data.raw_headers()["x-latin"].as_bytes() == b"caf\xe9");
+        assert!(data.header("x-latin").unwrap() == "caf\u{fffd}");
+        assert!(data.headers()["x-latin"] == "caf\u{fffd}");
+
+        // the header set twice was sent twice
+        let text = data.text().to_ascii_lowercase();
+        assert!(text.contains("accept: text/plain\r\n") && text.contains("accept: text/html\r\n"));
+    });
+    assert!(ret == 0);
+}
+
+// serve a 200 response of n bytes on each connection, the body starts after the delay,
+// then a byte per interval
+fn slow(delay: u64, n: usize, interval: u64) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+                    line.clear();
+                }
+
+                let head = format!("HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n", n);
+                let _ = stream.write_all(head.as_bytes());
+                thread::sleep(std::time::Duration::from_millis(delay));
+                for _ in 0..n {
+                    if stream.write_all(b"x").is_err() {
+                        break;
+                    }
+                    thread::sleep(std::time::Duration::from_millis(interval));
+                }
+            });
+        }
+    });
+
+    return url;
+}
+
+#[test]
+fn test_timeouts() {
+    use std::time::{Duration, Instant};
+
+    // the read timeout, applied after the first send
+    let url = slow(500, 1, 0);
+    let mut c = Request::new(Method::GET, &url);
+    assert!(c.send("", |data| assert!(data.bytes() == b"x")) == 0);
+    c.read_timeout(0.2);
+    let start = Instant::now();
+    assert!(c.send("", |_| {}) != 0);
+    assert!(start.elapsed() < Duration::from_millis(450));
+
+    // the total timeout of a trickling body
+    let url = slow(0, 40, 50);
+    let mut c = Request::new(Method::GET, &url);
+    c.read_timeout(0.0).total_timeout(0.4);
+    let start = Instant::now();
+    assert!(c.send("", |_| {}) != 0);
+    assert!(start.elapsed() >= Duration::from_millis(400) && start.elapsed() < Duration::from_millis(1500));
+
+    // the total timeout checked between the reads by a shorter read timeout
+    let mut c = Request::new(Method::GET, &url);
+    c.read_timeout(0.2).timeout(0.4);
+    let start = Instant::now();
+    assert!(c.send("", |_| {}) != 0);
+    assert!(c.error().contains("timed out"));
+    assert!(start.elapsed() >= Duration::from_millis(400) && start.elapsed() < Duration::from_millis(1500));
+
+    // the default read timeout never caps a longer total timeout
+    let url = serve(|_, _| {
+        thread::sleep(Duration::from_millis(10_500));
+        return response("200 OK", &[], b"late");
+    });
+    let mut c = Request::new(Method::GET, &url);
+    c.timeout(15.0);
+    assert!(c.send("", |data| assert!(data.bytes() == b"late")) == 0);
+
+    // the connect timeout, to a non-routable address
+    let mut c = Request::new(Method::GET, "http://10.255.255.1/");
+    c.connect_timeout(0.2);
+    let start = Instant::now();
+    assert!(c.send("", |_| {}) != 0);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}